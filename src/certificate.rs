@@ -0,0 +1,299 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use rug::{ops::Pow, Complete, Integer};
+
+use crate::{
+    driver::{self, TRIAL_DIVISION_BOUND},
+    factorizer::Error,
+    nfs::Statistics,
+    nt,
+    primes::Primes,
+};
+
+// Witnesses are searched for below this bound. For a prime n, a fails for q with probability 1 / q.
+const WITNESS_BOUND: u64 = 1000;
+
+// A proof that n is prime by Pocklington's theorem: if F divides n - 1 and F^2 > n, and for each
+// prime q dividing F there is an a with a^(n - 1) = 1 and gcd(a^((n - 1) / q) - 1, n) = 1 modulo
+// n, then each prime divisor of n is 1 modulo F, so n is prime. factors lists each q with its
+// exponent in F and the witness a. If F = n - 1, this is a Pratt certificate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Step {
+    n: Integer,
+    factors: Vec<(Integer, u32, u64)>,
+}
+
+impl Step {
+    // Checks the conditions of the theorem, given the primes proven so far.
+    fn verify(&self, proven: &HashSet<Integer>) -> Result<(), String> {
+        let n = &self.n;
+        if *n < 2 {
+            return Err(format!("{} is less than 2", n));
+        }
+        if self.factors.is_empty() {
+            return match *n == 2 {
+                true => Ok(()),
+                false => Err(format!("no factors of {} - 1 given", n)),
+            };
+        }
+
+        let n_minus_1 = (n - 1u32).complete();
+        let mut f = Integer::from(1);
+        for (q, e, a) in &self.factors {
+            if !proven.contains(q) {
+                return Err(format!("{} is used before it is proven", q));
+            }
+            f *= q.pow(*e).complete();
+            let a = Integer::from(*a);
+            let pow_mod = |e: &Integer| {
+                a.pow_mod_ref(e, n)
+                    .map(Integer::from)
+                    .ok_or_else(|| format!("{}^{} modulo {} is undefined", a, e, n))
+            };
+            if pow_mod(&n_minus_1)? != 1 {
+                return Err(format!("{}^({} - 1) is not 1 modulo {}", a, n, n));
+            }
+            let x = pow_mod(&(&n_minus_1 / q).complete())? - 1u32;
+            if x.gcd(n) != 1 {
+                return Err(format!("{} is no witness for {} in {} - 1", a, q, n));
+            }
+        }
+        if !n_minus_1.is_divisible(&f) {
+            return Err(format!("the factors don't divide {} - 1", n));
+        }
+        if f.square() <= *n {
+            return Err(format!("the factors of {} - 1 are too small", n));
+        }
+        Ok(())
+    }
+}
+
+// Primality certificates for a set of primes: a list of Pocklington steps, in which each prime q
+// used by a step is proven by an earlier one. The text format has one line per step,
+// "n q^e:a q^e:a ...", and the line "2" for the only prime that needs no proof.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Certificate {
+    steps: Vec<Step>,
+}
+
+impl Certificate {
+    // The primes proven by the certificate, if it verifies.
+    pub fn primes(&self) -> impl Iterator<Item = &Integer> {
+        self.steps.iter().map(|step| &step.n)
+    }
+
+    // Checks all steps, returning the first one that fails.
+    pub fn verify(&self) -> Result<(), String> {
+        let mut proven: HashSet<Integer> = HashSet::new();
+        for step in &self.steps {
+            step.verify(&proven)?;
+            proven.insert(step.n.clone());
+        }
+        Ok(())
+    }
+}
+
+impl Display for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            write!(f, "{}", step.n)?;
+            for (q, e, a) in &step.factors {
+                write!(f, " {}^{}:{}", q, e, a)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Certificate {
+    type Err = String;
+
+    // Empty lines and lines starting with '#' are skipped.
+    fn from_str(s: &str) -> Result<Certificate, String> {
+        let parse_integer = |s: &str| -> Result<Integer, String> {
+            Integer::from_str(s).map_err(|_| format!("invalid integer {}", s))
+        };
+
+        let mut steps: Vec<Step> = Vec::new();
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let n = parse_integer(parts.next().unwrap())?;
+            let mut factors: Vec<(Integer, u32, u64)> = Vec::new();
+            for part in parts {
+                let (power, a) = part
+                    .split_once(':')
+                    .ok_or_else(|| format!("expected q^e:a: {}", part))?;
+                let (q, e) = power
+                    .split_once('^')
+                    .ok_or_else(|| format!("expected q^e: {}", power))?;
+                factors.push((
+                    parse_integer(q)?,
+                    e.parse().map_err(|_| format!("invalid exponent {}", e))?,
+                    a.parse().map_err(|_| format!("invalid witness {}", a))?,
+                ));
+            }
+            steps.push(Step { n, factors });
+        }
+        Ok(Certificate { steps })
+    }
+}
+
+// Returns prime factors q of n - 1 with their exponents, whose product F satisfies F^2 > n. After
+// trial division, the composite parts are split with split, smallest first, until enough is known.
+fn pocklington_factors<F>(n: &Integer, split: &mut F) -> Result<Vec<(Integer, u32)>, Error>
+where
+    F: FnMut(&Integer) -> Result<(Integer, Option<Statistics>), Error>,
+{
+    let n_minus_1 = (n - 1u32).complete();
+    let enough = |f: &Integer| f.square_ref().complete() > *n;
+    let mut factors: Vec<(Integer, u32)> = Vec::new();
+    let mut f = Integer::from(1);
+    let mut rest = n_minus_1.clone();
+
+    for p in Primes::new(2).take_while(|&p| p < TRIAL_DIVISION_BOUND) {
+        if rest == 1 || enough(&f) {
+            break;
+        }
+        let p = Integer::from(p);
+        let e = rest.remove_factor_mut(&p);
+        if e != 0 {
+            f *= (&p).pow(e).complete();
+            factors.push((p, e));
+        }
+    }
+
+    // The parts of rest still to be factored, which are odd.
+    let mut parts: Vec<Integer> = Vec::new();
+    if rest > 1 {
+        parts.push(rest);
+    }
+    while !enough(&f) {
+        // F = n - 1 would be enough, so some part is left.
+        parts.sort_unstable_by(|a, b| b.cmp(a));
+        let m = parts.pop().unwrap();
+        if factors.iter().any(|(q, _)| *q == m) {
+            continue;
+        }
+        if nt::bpsw(&m) {
+            let e = n_minus_1.remove_factor_ref(&m).complete().1;
+            f *= (&m).pow(e).complete();
+            factors.push((m, e));
+        } else if let Some((r, _)) = driver::perfect_power(&m) {
+            parts.push(r);
+        } else {
+            let (d, _) = split(&m)?;
+            parts.push((&m / &d).complete());
+            parts.push(d);
+        }
+    }
+    Ok(factors)
+}
+
+// Adds the steps proving n and the primes its step depends on, unless they are proven already.
+fn add_steps<F>(
+    n: &Integer,
+    split: &mut F,
+    proven: &mut HashSet<Integer>,
+    steps: &mut Vec<Step>,
+) -> Result<(), Error>
+where
+    F: FnMut(&Integer) -> Result<(Integer, Option<Statistics>), Error>,
+{
+    if proven.contains(n) {
+        return Ok(());
+    }
+    let not_prime = || Error::InvalidInput(format!("{} is not a prime", n));
+    // 2 needs no proof.
+    let mut factors: Vec<(Integer, u32, u64)> = Vec::new();
+    if *n != 2 {
+        if !nt::bpsw(n) {
+            return Err(not_prime());
+        }
+        let n_minus_1 = (n - 1u32).complete();
+        for (q, e) in pocklington_factors(n, split)? {
+            add_steps(&q, split, proven, steps)?;
+            let exponent = (&n_minus_1 / &q).complete();
+            let a = (2..WITNESS_BOUND)
+                .find(|&a| {
+                    let a = Integer::from(a);
+                    a.pow_mod_ref(&n_minus_1, n).unwrap().complete() == 1
+                        && (a.pow_mod(&exponent, n).unwrap() - 1u32).gcd(n) == 1
+                })
+                .ok_or_else(not_prime)?;
+            factors.push((q, e, a));
+        }
+    }
+    steps.push(Step {
+        n: n.clone(),
+        factors,
+    });
+    proven.insert(n.clone());
+    Ok(())
+}
+
+// Proves that the given numbers are prime. The factors of p - 1 that are needed are found with
+// split, as in driver::factor.
+pub fn certify<F>(primes: &[Integer], mut split: F) -> Result<Certificate, Error>
+where
+    F: FnMut(&Integer) -> Result<(Integer, Option<Statistics>), Error>,
+{
+    let mut proven: HashSet<Integer> = HashSet::new();
+    let mut steps: Vec<Step> = Vec::new();
+    for p in primes {
+        add_steps(p, &mut split, &mut proven, &mut steps)?;
+    }
+    Ok(Certificate { steps })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Factorizer;
+
+    #[test]
+    fn certify_and_verify() {
+        let m89 = Integer::from(2).pow(89) - 1u32;
+        let m127 = Integer::from(2).pow(127) - 1u32;
+        let primes = [
+            Integer::from(2),
+            Integer::from(65537),
+            Integer::from(1_000_000_007),
+            Integer::from(u64::MAX - 58),
+            m89.clone(),
+            m127.clone(),
+        ];
+        let certificate = Factorizer::new().certify(&primes).unwrap();
+        assert_eq!(certificate.verify(), Ok(()));
+        for p in &primes {
+            assert!(certificate.primes().any(|q| q == p));
+        }
+        let parsed: Certificate = certificate.to_string().parse().unwrap();
+        assert_eq!(parsed, certificate);
+        assert!(Factorizer::new().certify(&[m89 * m127]).is_err());
+
+        // 5 - 1 = 2^2, with the witness 2 but not 4, since 4^2 = 1 modulo 5. The step for the
+        // composite 15 with 15 - 1 = 2 * 7 is rejected because 2^(15 - 1) = 4 modulo 15, not 1.
+        let check = |s: &str| s.parse::<Certificate>().unwrap().verify();
+        assert_eq!(check("2\n# comment\n5 2^2:2\n"), Ok(()));
+        assert!(check("2\n5 2^2:4").is_err());
+        assert!(check("5 2^2:2\n2").is_err());
+        assert!(check("2\n5 2^1:2").is_err());
+        assert!(check("3").is_err());
+        assert_eq!(check("2\n3 2^1:2\n7 2^1:3 3^1:3"), Ok(()));
+        assert!(check("2\n3 2^1:2\n7 2^1:3 3^1:3\n15 2^1:2 7^1:2").is_err());
+        assert!("2\n5 2:2".parse::<Certificate>().is_err());
+
+        // Numbers below 2 are rejected before the witnesses are checked.
+        assert!(check("2\n-9 2^1:8").is_err());
+        assert!(check("2\n0 2^1:3").is_err());
+        assert!(check("1").is_err());
+    }
+}
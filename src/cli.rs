@@ -0,0 +1,235 @@
+use nfs::{Params, SpecialForm};
+
+pub const USAGE: &str = "\
+Usage: nfs [OPTIONS] [NUMBER]...
+
+Factors each NUMBER into primes. Small factors are found with Pollard's rho and p - 1 methods and
+the elliptic curve method, the remaining composites are split with the quadratic sieve or, above
+the crossover size, the number field sieve. If no numbers and no input file are given, the numbers
+are read from stdin, one per line.
+
+Options:
+  -i, --input <FILE>        read numbers from FILE, one per line ('-' for stdin)
+  -p, --params-file <FILE>  read parameter overrides from FILE (lines of the form name = value)
+      --<name> <VALUE>      override a single parameter, e.g. --sieve-array-size 50000
+  -f, --format <FORMAT>     output format: plain (default) or json (one object per line)
+  -j, --job-dir <DIR>       keep the state of the number field sieve in DIR and resume from it
+      --import-relations <FILE>  use the relations in FILE (msieve / CADO-NFS format) in addition
+                            to those found by sieving
+      --export-relations <FILE>  write all relations to FILE in the same format after sieving
+      --poly <FILE>         use the polynomial pair in FILE (msieve / CADO-NFS .poly format) for
+                            the number field sieve on the number it belongs to, which is factored
+                            if no other numbers are given
+      --export-poly <FILE>  write the polynomial pair to FILE in the same format
+      --snfs <FORM>         use the special number field sieve for the divisors of FORM, a number
+                            a*b^k+c like 2^512+1, which is factored if no other numbers are given
+      --certify <FILE>      prove the prime factors with Pocklington certificates and write them to
+                            FILE, one line 'p q^e:a ...' per prime after the primes q it uses
+      --nfs-crossover <BITS>  use the number field sieve only for composites with more bits
+                            (default 260), smaller ones are split with the quadratic sieve
+  -v, --verbose             increase the log level (can be repeated)
+  -q, --quiet               only log errors
+  -h, --help                print this help
+  -V, --version             print the version
+
+Parameters:
+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Plain,
+    Json,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Options {
+    pub numbers: Vec<String>,
+    pub input: Option<String>,
+    pub params_file: Option<String>,
+    // Parameter overrides from the command line, applied after the ones from the params file.
+    pub overrides: Vec<(String, String)>,
+    pub format: Format,
+    pub nfs_crossover: Option<u32>,
+    pub job_dir: Option<String>,
+    pub import_relations: Option<String>,
+    pub export_relations: Option<String>,
+    pub poly: Option<String>,
+    pub export_poly: Option<String>,
+    pub snfs: Option<SpecialForm>,
+    pub certify: Option<String>,
+    // 0 means only errors are logged, 1 warnings (default), 2 info, 3 debug and 4 trace.
+    pub verbosity: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Version,
+    Run(Box<Options>),
+}
+
+pub fn usage() -> String {
+    let mut s = USAGE.to_string();
+    for name in Params::NAMES {
+        s.push_str(&format!("      --{}\n", name.replace('_', "-")));
+    }
+    s
+}
+
+pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let mut options = Options {
+        numbers: Vec::new(),
+        input: None,
+        params_file: None,
+        overrides: Vec::new(),
+        format: Format::Plain,
+        nfs_crossover: None,
+        job_dir: None,
+        import_relations: None,
+        export_relations: None,
+        poly: None,
+        export_poly: None,
+        snfs: None,
+        certify: None,
+        verbosity: 1,
+    };
+
+    while let Some(arg) = args.next() {
+        // Allow both "--name value" and "--name=value".
+        let (arg, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if arg.starts_with("--") => (name.to_string(), Some(value.into())),
+            _ => (arg, None),
+        };
+        let mut value = |name: &str| -> Result<String, String> {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("missing value for {}", name))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "--verbose" => options.verbosity += 1,
+            // -v, -vv, -vvv and so on.
+            _ if arg.len() > 1 && arg.starts_with('-') && arg[1..].bytes().all(|c| c == b'v') => {
+                options.verbosity += arg.len() as u32 - 1
+            }
+            "-q" | "--quiet" => options.verbosity = 0,
+            "-i" | "--input" => options.input = Some(value(&arg)?),
+            "-p" | "--params-file" => options.params_file = Some(value(&arg)?),
+            "-j" | "--job-dir" => options.job_dir = Some(value(&arg)?),
+            "--import-relations" => options.import_relations = Some(value(&arg)?),
+            "--export-relations" => options.export_relations = Some(value(&arg)?),
+            "--poly" => options.poly = Some(value(&arg)?),
+            "--export-poly" => options.export_poly = Some(value(&arg)?),
+            "--snfs" => options.snfs = Some(value(&arg)?.parse()?),
+            "--certify" => options.certify = Some(value(&arg)?),
+            "-f" | "--format" => {
+                options.format = match value(&arg)?.as_str() {
+                    "plain" => Format::Plain,
+                    "json" => Format::Json,
+                    format => return Err(format!("unknown output format {}", format)),
+                }
+            }
+            "--nfs-crossover" => {
+                let bits = value(&arg)?;
+                options.nfs_crossover = Some(
+                    bits.parse()
+                        .map_err(|_| format!("invalid value {} for {}", bits, arg))?,
+                );
+            }
+            _ if arg.starts_with("--") => {
+                let name = arg[2..].replace('-', "_");
+                if !Params::NAMES.contains(&name.as_str()) {
+                    return Err(format!("unknown option {}", arg));
+                }
+                options.overrides.push((name, value(&arg)?));
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(format!("unknown option {}", arg))
+            }
+            _ => options.numbers.push(arg),
+        }
+    }
+
+    Ok(Command::Run(Box::new(options)))
+}
+
+// Extracts the numbers from the contents of an input file. Empty lines and lines starting with '#'
+// are skipped.
+pub fn numbers_from_str(s: &str) -> Vec<String> {
+    s.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+// Escapes a string for use in a JSON string literal.
+pub fn json_escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> impl Iterator<Item = String> + '_ {
+        s.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn parse_options() {
+        let command = parse(args(
+            "-vv 91 --sieve-array-size 5000 -f json --rational-fudge=-2 -p p.txt 1001 -j jobs \
+             --export-relations=rels.out --poly c100.poly --certify primes.cert",
+        ))
+        .unwrap();
+        assert_eq!(
+            command,
+            Command::Run(Box::new(Options {
+                numbers: vec!["91".into(), "1001".into()],
+                input: None,
+                params_file: Some("p.txt".into()),
+                overrides: vec![
+                    ("sieve_array_size".into(), "5000".into()),
+                    ("rational_fudge".into(), "-2".into())
+                ],
+                format: Format::Json,
+                nfs_crossover: None,
+                job_dir: Some("jobs".into()),
+                import_relations: None,
+                export_relations: Some("rels.out".into()),
+                poly: Some("c100.poly".into()),
+                export_poly: None,
+                snfs: None,
+                certify: Some("primes.cert".into()),
+                verbosity: 3,
+            }))
+        );
+
+        match parse(args("-vvvv 5 -v")).unwrap() {
+            Command::Run(options) => assert_eq!(options.verbosity, 6),
+            command => panic!("{:?}", command),
+        }
+        assert!(parse(args("-vx 5")).is_err());
+
+        assert_eq!(parse(args("1 --help")).unwrap(), Command::Help);
+        assert!(parse(args("--no-such-param 3")).is_err());
+        assert!(parse(args("--format xml")).is_err());
+        assert!(parse(args("--nfs-crossover x")).is_err());
+        assert!(parse(args("--input")).is_err());
+        assert!(parse(args("--snfs 2^x+1")).is_err());
+    }
+}
@@ -0,0 +1,340 @@
+use rand::{thread_rng, Rng};
+use rug::Integer;
+
+use crate::nt::{self, Montgomery};
+
+// Multipliers for SQUFOF, products of small odd primes.
+const SQUFOF_MULTIPLIERS: [u128; 16] = [
+    1, 3, 5, 7, 11, 15, 21, 33, 35, 55, 77, 105, 165, 231, 385, 1155,
+];
+
+// Stage 1 bound and number of curves for ECM on word-size numbers.
+const ECM_B1: u64 = 600;
+const ECM_CURVES: usize = 40;
+
+fn isqrt(n: u128) -> u128 {
+    if n == 0 {
+        return 0;
+    }
+    let mut x = (n as f64).sqrt() as u128;
+    while x * x > n {
+        x -= 1;
+    }
+    while (x + 1) * (x + 1) <= n {
+        x += 1;
+    }
+    x
+}
+
+// Returns g if it is a nontrivial factor of n.
+fn nontrivial(g: u64, n: u64) -> Option<u64> {
+    (g != 1 && g != n).then_some(g)
+}
+
+// Shanks' square forms factorization. Works for odd composite n that is not a perfect square.
+pub fn squfof(n: u64) -> Option<u64> {
+    let n = n as u128;
+    let s = isqrt(n);
+    if s * s == n {
+        return Some(s as u64);
+    }
+
+    for k in SQUFOF_MULTIPLIERS {
+        let d = (k * n) as i128;
+        let p0 = isqrt(d as u128) as i128;
+        let mut q = d - p0 * p0;
+        if q == 0 {
+            if let Some(g) = nontrivial(nt::gcd(n as u64, p0 as u64 % n as u64), n as u64) {
+                return Some(g);
+            }
+            continue;
+        }
+
+        // Forward cycle: search for a square form Q at an even index.
+        let bound = 3 * 2 * isqrt(2 * s) as i128;
+        let (mut p, mut p_prev, mut q_prev) = (p0, p0, 1i128);
+        let mut r = 0i128;
+        let mut i = 2;
+        while i < bound {
+            let b = (p0 + p) / q;
+            p = b * q - p;
+            let q_old = q;
+            q = q_prev + b * (p_prev - p);
+            r = isqrt(q as u128) as i128;
+            if i & 1 == 0 && r * r == q {
+                break;
+            }
+            q_prev = q_old;
+            p_prev = p;
+            i += 1;
+        }
+        if i >= bound {
+            continue;
+        }
+
+        // Reverse cycle: start from the square root of the form and wait for P to repeat.
+        let b = (p0 - p) / r;
+        p += b * r;
+        q_prev = r;
+        q = (d - p * p) / q_prev;
+        for _ in 0..bound {
+            let b = (p0 + p) / q;
+            p_prev = p;
+            p = b * q - p;
+            let q_old = q;
+            q = q_prev + b * (p_prev - p);
+            q_prev = q_old;
+            if p == p_prev {
+                break;
+            }
+        }
+
+        if let Some(g) = nontrivial(nt::gcd(n as u64, q_prev as u64 % n as u64), n as u64) {
+            return Some(g);
+        }
+    }
+
+    None
+}
+
+// Pollard's rho method with Brent's cycle detection on odd word-size numbers, see rho::rho. The
+// iteration runs in Montgomery form, which only multiplies the differences by units modulo n.
+pub fn rho(n: u64, c: u64, max_iterations: u64) -> Option<u64> {
+    const BATCH_SIZE: u64 = 128;
+
+    let montgomery = Montgomery::new(n);
+    let c = montgomery.to_montgomery(c % n);
+    let f = |x: u64| nt::mod_add(montgomery.mul(x, x), c, n);
+    let start = montgomery.to_montgomery(2 % n);
+    let (mut x, mut y, mut ys) = (start, start, start);
+    let (mut q, mut g) = (1, 1);
+    let mut r: u64 = 1;
+    let mut iterations: u64 = 0;
+
+    while g == 1 {
+        x = y;
+        for _ in 0..r {
+            y = f(y);
+        }
+
+        let mut k = 0;
+        while k < r && g == 1 {
+            ys = y;
+            for _ in 0..BATCH_SIZE.min(r - k) {
+                y = f(y);
+                q = montgomery.mul(q, x.abs_diff(y));
+            }
+            g = nt::gcd(q, n);
+            k += BATCH_SIZE;
+        }
+
+        iterations += 2 * r;
+        if iterations > max_iterations && g == 1 {
+            return None;
+        }
+        r <<= 1;
+    }
+
+    if g == n {
+        loop {
+            ys = f(ys);
+            g = nt::gcd(x.abs_diff(ys), n);
+            if g != 1 {
+                break;
+            }
+        }
+    }
+
+    nontrivial(g, n)
+}
+
+// Arithmetic on points (x : z) of the Montgomery curve with (a + 2) / 4 = a24, see ecm.rs. All
+// coordinates are in Montgomery form modulo n.
+fn ecm_double(x: u64, z: u64, a24: u64, m: &Montgomery, n: u64) -> (u64, u64) {
+    let (sum, difference) = (nt::mod_add(x, z, n), nt::mod_sub(x, z, n));
+    let s = m.mul(sum, sum);
+    let d = m.mul(difference, difference);
+    let t = nt::mod_sub(s, d, n);
+    (m.mul(s, d), m.mul(t, nt::mod_add(d, m.mul(a24, t), n)))
+}
+
+fn ecm_add(
+    p: (u64, u64),
+    q: (u64, u64),
+    difference: (u64, u64),
+    m: &Montgomery,
+    n: u64,
+) -> (u64, u64) {
+    let u = m.mul(nt::mod_sub(p.0, p.1, n), nt::mod_add(q.0, q.1, n));
+    let v = m.mul(nt::mod_add(p.0, p.1, n), nt::mod_sub(q.0, q.1, n));
+    let (s, d) = (nt::mod_add(u, v, n), nt::mod_sub(u, v, n));
+    (
+        m.mul(m.mul(s, s), difference.1),
+        m.mul(m.mul(d, d), difference.0),
+    )
+}
+
+fn ecm_mul(p: (u64, u64), k: u64, a24: u64, m: &Montgomery, n: u64) -> (u64, u64) {
+    let mut r0 = p;
+    let mut r1 = ecm_double(p.0, p.1, a24, m, n);
+    for i in (0..k.ilog2()).rev() {
+        if (k >> i) & 1 == 1 {
+            r0 = ecm_add(r1, r0, p, m, n);
+            r1 = ecm_double(r1.0, r1.1, a24, m, n);
+        } else {
+            r1 = ecm_add(r0, r1, p, m, n);
+            r0 = ecm_double(r0.0, r0.1, a24, m, n);
+        }
+    }
+    r0
+}
+
+// Stage 1 of the elliptic curve method with Montgomery curves in Suyama's parametrization, on odd
+// word-size numbers.
+pub fn ecm(n: u64, b1: u64, num_curves: usize) -> Option<u64> {
+    let primes = nt::primes_below(b1 + 1);
+    let montgomery = Montgomery::new(n);
+    let mut rng = thread_rng();
+
+    for _ in 0..num_curves {
+        let sigma = rng.gen_range(6..n.min(1 << 32));
+        let u = nt::mod_sub(nt::mod_mul(sigma, sigma, n), 5 % n, n);
+        let v = nt::mod_mul(4, sigma, n);
+        let x = nt::mod_mul(nt::mod_mul(u, u, n), u, n);
+        let z = nt::mod_mul(nt::mod_mul(v, v, n), v, n);
+
+        let w = nt::mod_sub(v, u, n);
+        let numerator = nt::mod_mul(
+            nt::mod_mul(nt::mod_mul(w, w, n), w, n),
+            nt::mod_add(nt::mod_mul(3, u, n), v, n),
+            n,
+        );
+        let denominator = nt::mod_mul(16, nt::mod_mul(x, v, n), n);
+        let a24 = match nt::invert(denominator, n) {
+            Some(inverse) => nt::mod_mul(numerator, inverse, n),
+            None => match nontrivial(nt::gcd(denominator, n), n) {
+                Some(g) => return Some(g),
+                None => continue,
+            },
+        };
+
+        let a24 = montgomery.to_montgomery(a24);
+        let mut q = (montgomery.to_montgomery(x), montgomery.to_montgomery(z));
+        for &p in &primes {
+            let mut k = p;
+            while k <= b1 / p {
+                k *= p;
+            }
+            q = ecm_mul(q, k, a24, &montgomery, n);
+        }
+
+        // z * 2^64 has the same common factors with n as z.
+        if let Some(g) = nontrivial(nt::gcd(q.1, n), n) {
+            return Some(g);
+        }
+    }
+
+    None
+}
+
+// Finds a nontrivial factor of the odd composite n, choosing the method by the size of n: rho finds
+// the small factors of small numbers fastest, SQUFOF is used up to 62 bits and ECM above.
+pub fn split(n: u64) -> Option<u64> {
+    if n.is_multiple_of(2) {
+        return nontrivial(2, n);
+    }
+
+    let bits = n.ilog2() + 1;
+    if bits <= 42 {
+        if let Some(g) = rho(n, 1, 1 << 14) {
+            return Some(g);
+        }
+    }
+    if bits <= 62 {
+        if let Some(g) = squfof(n) {
+            return Some(g);
+        }
+    }
+    if let Some(g) = ecm(n, ECM_B1, ECM_CURVES) {
+        return Some(g);
+    }
+    (1..8).find_map(|c| rho(n, c, 1 << 20))
+}
+
+// Writes the cofactor c left over after trial division as a product of at most max_primes primes
+// below bound, which must be below 2^32. Returns None if this is not possible, so that the
+// candidate can be discarded.
+pub fn large_primes(c: &Integer, bound: u64, max_primes: usize) -> Option<Vec<u64>> {
+    if *c == 1 {
+        return Some(Vec::new());
+    }
+    if max_primes == 0 || *c > Integer::from(bound).square() {
+        return None;
+    }
+    let c = c.to_u64().unwrap();
+    if nt::miller_rabin(c) {
+        return (c <= bound).then(|| vec![c]);
+    }
+    if max_primes == 1 {
+        return None;
+    }
+
+    // The square of a large prime is a valid relation, the cycle finder treats the repeated large
+    // prime as a cycle by itself. split doesn't handle squares, so they are checked first.
+    let r = isqrt(c as u128) as u64;
+    if r * r == c {
+        return (r <= bound && nt::miller_rabin(r)).then(|| vec![r, r]);
+    }
+
+    let p = split(c)?;
+    let q = c / p;
+    if p > bound || q > bound {
+        return None;
+    }
+    for x in [p, q] {
+        if !nt::miller_rabin(x) {
+            return None;
+        }
+    }
+    Some(vec![p.min(q), p.max(q)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_word_size() {
+        let semiprimes: [(u64, u64); 4] = [
+            (65537, 65539),
+            (1000003, 998244353),
+            (2147483647, 2147483659),
+            (4294967291, 4294967279),
+        ];
+        for (p, q) in semiprimes {
+            let n = p * q;
+            let check = |g: Option<u64>| assert!(g == Some(p) || g == Some(q), "{}", n);
+            check(split(n));
+            check(ecm(n, 2000, 200));
+            if n < 1 << 62 {
+                check(squfof(n));
+            }
+        }
+        assert_eq!(
+            rho(1000003 * 1000033, 1, 1 << 20).map(|g| 1000003 * 1000033 % g),
+            Some(0)
+        );
+
+        let c = Integer::from(65537u64 * 65539);
+        assert_eq!(large_primes(&c, 1 << 17, 2), Some(vec![65537, 65539]));
+        assert_eq!(large_primes(&c, 1 << 17, 1), None);
+        assert_eq!(large_primes(&c, 1 << 16, 2), None);
+        let c = Integer::from(65537u64 * 65537);
+        assert_eq!(large_primes(&c, 1 << 17, 2), Some(vec![65537, 65537]));
+        assert_eq!(large_primes(&c, 1 << 17, 1), None);
+        assert_eq!(
+            large_primes(&Integer::from(65537), 1 << 17, 1),
+            Some(vec![65537])
+        );
+    }
+}
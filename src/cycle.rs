@@ -0,0 +1,120 @@
+use std::{collections::HashMap, hash::Hash};
+
+// Finds cycles in the graph whose vertices are large primes and whose edges are relations. A
+// relation with two large primes connects them, a relation with a single large prime connects it to
+// the special vertex 1 (represented by None). The relations on a cycle multiply to a product in
+// which every large prime occurs to an even power, so they can be combined into one column of the
+// matrix.
+pub struct CycleFinder<K> {
+    ids: HashMap<K, usize>,
+    // Union-find structure over the vertices, vertex 0 is 1.
+    parent: Vec<usize>,
+    // Adjacency lists of a spanning forest of the graph, as (neighbour, relation).
+    forest: Vec<Vec<(usize, usize)>>,
+}
+
+impl<K: Hash + Eq> CycleFinder<K> {
+    pub fn new() -> CycleFinder<K> {
+        CycleFinder {
+            ids: HashMap::new(),
+            parent: vec![0],
+            forest: vec![Vec::new()],
+        }
+    }
+
+    fn vertex(&mut self, key: Option<K>) -> usize {
+        match key {
+            None => 0,
+            Some(key) => *self.ids.entry(key).or_insert_with(|| {
+                self.parent.push(self.parent.len());
+                self.forest.push(Vec::new());
+                self.parent.len() - 1
+            }),
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    // Returns the relations on the path from u to v in the spanning forest.
+    fn path(&self, u: usize, v: usize) -> Vec<usize> {
+        let mut predecessor: HashMap<usize, (usize, usize)> = HashMap::new();
+        let mut stack = vec![u];
+        predecessor.insert(u, (u, usize::MAX));
+
+        while let Some(x) = stack.pop() {
+            if x == v {
+                break;
+            }
+            for &(y, relation) in &self.forest[x] {
+                predecessor.entry(y).or_insert_with(|| {
+                    stack.push(y);
+                    (x, relation)
+                });
+            }
+        }
+
+        let mut relations: Vec<usize> = Vec::new();
+        let mut x = v;
+        while x != u {
+            let (y, relation) = predecessor[&x];
+            relations.push(relation);
+            x = y;
+        }
+        relations
+    }
+
+    // Adds the relation with index relation connecting u and v. If this closes a cycle, returns the
+    // indices of the relations on it.
+    pub fn add(&mut self, u: Option<K>, v: Option<K>, relation: usize) -> Option<Vec<usize>> {
+        let (u, v) = (self.vertex(u), self.vertex(v));
+        let (root_u, root_v) = (self.find(u), self.find(v));
+
+        if root_u == root_v {
+            let mut cycle = self.path(u, v);
+            cycle.push(relation);
+            Some(cycle)
+        } else {
+            self.parent[root_u] = root_v;
+            self.forest[u].push((v, relation));
+            self.forest[v].push((u, relation));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_cycles() {
+        let mut cycle_finder: CycleFinder<u64> = CycleFinder::new();
+        assert_eq!(cycle_finder.add(None, Some(101), 0), None);
+        assert_eq!(cycle_finder.add(Some(103), Some(107), 1), None);
+        assert_eq!(cycle_finder.add(Some(101), Some(107), 2), None);
+
+        // Two relations with the same single large prime.
+        let mut cycle = cycle_finder.add(Some(101), None, 3).unwrap();
+        cycle.sort_unstable();
+        assert_eq!(cycle, vec![0, 3]);
+
+        // 103 - 107 - 101 - 1
+        let mut cycle = cycle_finder.add(None, Some(103), 4).unwrap();
+        cycle.sort_unstable();
+        assert_eq!(cycle, vec![0, 1, 2, 4]);
+
+        assert_eq!(cycle_finder.add(Some(109), Some(113), 5), None);
+        let mut cycle = cycle_finder.add(Some(113), Some(109), 6).unwrap();
+        cycle.sort_unstable();
+        assert_eq!(cycle, vec![5, 6]);
+
+        // A relation with the square of a large prime is a cycle by itself.
+        assert_eq!(cycle_finder.add(Some(127), Some(127), 7), Some(vec![7]));
+    }
+}
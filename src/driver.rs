@@ -0,0 +1,190 @@
+use log::{debug, info};
+use rug::{Complete, Integer};
+
+use crate::{factorizer::Error, nfs::Statistics, nt, primes::Primes};
+
+// Primes below this bound are removed by trial division before anything else is tried.
+pub const TRIAL_DIVISION_BOUND: u64 = 1 << 16;
+
+// The complete factorization of n into primes. The primes are sorted and distinct, each with its
+// multiplicity. Primes below 2^64 are proven, the larger ones passed the Baillie-PSW test and are
+// listed in probable_primes. statistics contains an entry for each run of the number field sieve.
+#[derive(Clone, Debug)]
+pub struct PrimeFactorization {
+    pub n: Integer,
+    pub factors: Vec<(Integer, u32)>,
+    pub probable_primes: Vec<Integer>,
+    pub statistics: Vec<Statistics>,
+}
+
+// Returns (r, k) with n = r^k and k maximal among prime exponents, or None if n is no perfect
+// power.
+pub(crate) fn perfect_power(n: &Integer) -> Option<(Integer, u32)> {
+    if !n.is_perfect_power() {
+        return None;
+    }
+    for k in (2..=n.significant_bits()).rev() {
+        if nt::miller_rabin(k as u64) {
+            let (r, rem) = n.root_rem_ref(k).complete();
+            if rem == 0 {
+                return Some((r, k));
+            }
+        }
+    }
+    None
+}
+
+// Factors n completely. Composite parts that are too large to be handled by trial division are
+// split with split, which must return a nontrivial divisor of its argument and the statistics of
+// the number field sieve, if it was used.
+pub fn factor<F>(n: &Integer, mut split: F) -> Result<PrimeFactorization, Error>
+where
+    F: FnMut(&Integer) -> Result<(Integer, Option<Statistics>), Error>,
+{
+    if *n < 1 {
+        return Err(Error::InvalidInput(format!(
+            "{} has no prime factorization",
+            n
+        )));
+    }
+
+    let mut factors: Vec<(Integer, u32)> = Vec::new();
+    let mut statistics: Vec<Statistics> = Vec::new();
+    let mut m = n.clone();
+
+    for p in Primes::new(2).take_while(|&p| p < TRIAL_DIVISION_BOUND) {
+        if m == 1 {
+            break;
+        }
+        let e = m.remove_factor_mut(&Integer::from(p));
+        if e != 0 {
+            factors.push((Integer::from(p), e));
+        }
+    }
+
+    // Composite parts, which have no prime factor below the trial division bound, together with
+    // the exponent they occur with in n.
+    let mut composites: Vec<(Integer, u32)> = Vec::new();
+    if m > 1 {
+        composites.push((m, 1));
+    }
+
+    while let Some((m, e)) = composites.pop() {
+        if nt::bpsw(&m) {
+            factors.push((m, e));
+            continue;
+        }
+
+        if let Some((r, k)) = perfect_power(&m) {
+            debug!("{} is the {}-th power of {}", m, k, r);
+            composites.push((r, e * k));
+            continue;
+        }
+
+        // Since all prime factors are at least TRIAL_DIVISION_BOUND, m is a product of two primes
+        // if it is below the cube of that bound, and the smaller one is found quickly by trial
+        // division.
+        let d = if m.significant_bits() <= 3 * TRIAL_DIVISION_BOUND.ilog2() {
+            let x = m.to_u64().unwrap();
+            let mut d = TRIAL_DIVISION_BOUND | 1;
+            while x % d != 0 {
+                d += 2;
+            }
+            Integer::from(d)
+        } else {
+            info!("splitting {} ({} bits)", m, m.significant_bits());
+            let (d, s) = split(&m)?;
+            statistics.extend(s);
+            d
+        };
+
+        debug!("found the divisor {} of {}", d, m);
+        let cofactor = (&m / &d).complete();
+        composites.push((d, e));
+        composites.push((cofactor, e));
+    }
+
+    factors.sort_unstable();
+    let mut merged: Vec<(Integer, u32)> = Vec::new();
+    for (p, e) in factors {
+        match merged.last_mut() {
+            Some((q, f)) if *q == p => *f += e,
+            _ => merged.push((p, e)),
+        }
+    }
+
+    let probable_primes: Vec<Integer> = merged
+        .iter()
+        .map(|(p, _)| p)
+        .filter(|p| p.to_u64().is_none())
+        .cloned()
+        .collect();
+    for p in &probable_primes {
+        debug!("{} is a probable prime", p);
+    }
+
+    Ok(PrimeFactorization {
+        n: n.clone(),
+        factors: merged,
+        probable_primes,
+        statistics,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rug::ops::Pow;
+
+    use super::*;
+
+    const PRIMES: [u32; 4] = [65537, 65539, 1000003, 1000000007];
+
+    fn no_split(n: &Integer) -> Result<(Integer, Option<Statistics>), Error> {
+        panic!("{} should not need to be split", n);
+    }
+
+    fn naive_split(n: &Integer) -> Result<(Integer, Option<Statistics>), Error> {
+        for p in PRIMES {
+            if n.is_divisible_u(p) && *n != p {
+                return Ok((Integer::from(p), None));
+            }
+        }
+        Err(Error::NoFactorFound)
+    }
+
+    #[test]
+    fn factor_small_primes_and_powers() {
+        // 2^4 * 3 * 65537^2 * 65539 * (1000003^3) * 1000000007
+        let n = Integer::from(48u32)
+            * Integer::from(65537u32).square()
+            * Integer::from(65539u32)
+            * Integer::from(1000003u32).pow(3)
+            * Integer::from(1000000007u32);
+        let factorization = factor(&n, naive_split).unwrap();
+        assert_eq!(
+            factorization.factors,
+            vec![
+                (Integer::from(2), 4),
+                (Integer::from(3), 1),
+                (Integer::from(65537), 2),
+                (Integer::from(65539), 1),
+                (Integer::from(1000003), 3),
+                (Integer::from(1000000007), 1)
+            ]
+        );
+
+        let n = Integer::from(65537u32) * Integer::from(1000003u32);
+        assert_eq!(factor(&n, no_split).unwrap().factors.len(), 2);
+        assert!(factor(&Integer::from(1), no_split)
+            .unwrap()
+            .factors
+            .is_empty());
+        assert!(factor(&Integer::from(0), no_split).is_err());
+
+        // Only the prime above 2^64 is not proven.
+        let m89 = Integer::from(2).pow(89u32) - 1u32;
+        let factorization = factor(&(Integer::from(48u32) * &m89), no_split).unwrap();
+        assert_eq!(factorization.factors.len(), 3);
+        assert_eq!(factorization.probable_primes, vec![m89]);
+    }
+}
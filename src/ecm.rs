@@ -0,0 +1,210 @@
+use rand::{thread_rng, Rng};
+use rug::{Complete, Integer};
+
+use crate::nt;
+
+// Product of the first primes, used as the giant step size in stage 2.
+const D: u64 = 2 * 3 * 5 * 7;
+
+// A point on a Montgomery curve b * y^2 = x^3 + a * x^2 + x in projective coordinates (x : z).
+// The y-coordinate is not needed for the differential addition chains used here.
+#[derive(Clone, Debug)]
+struct Point {
+    x: Integer,
+    z: Integer,
+}
+
+struct Curve<'a> {
+    n: &'a Integer,
+    a24: Integer, // (a + 2) / 4
+}
+
+// Either a curve with a starting point on it, or a factor of n found while trying to construct it.
+enum CurveOrFactor<'a> {
+    Curve(Curve<'a>, Point),
+    Factor(Integer),
+}
+
+impl<'a> Curve<'a> {
+    // Suyama's parametrization, which yields curves whose group order is divisible by 12.
+    fn suyama(n: &'a Integer, sigma: u64) -> Option<CurveOrFactor<'a>> {
+        let sigma = Integer::from(sigma);
+        let u = (sigma.square_ref().complete() - 5u32) % n;
+        let v = (&sigma * 4u32).complete() % n;
+
+        let x = u.clone().pow_mod(&Integer::from(3), n).unwrap();
+        let z = v.clone().pow_mod(&Integer::from(3), n).unwrap();
+
+        let numerator = (&v - &u).complete().pow_mod(&Integer::from(3), n).unwrap()
+            * (&u * 3u32 + &v).complete();
+        let denominator = (&x * &v).complete() * 16u32 % n;
+
+        match denominator.invert_ref(n) {
+            Some(inverse) => {
+                let a24 = numerator * Integer::from(inverse) % n;
+                Some(CurveOrFactor::Curve(Curve { n, a24 }, Point { x, z }))
+            }
+            None => {
+                let g = denominator.gcd(n);
+                if g == 1 || g == *n {
+                    None
+                } else {
+                    Some(CurveOrFactor::Factor(g))
+                }
+            }
+        }
+    }
+
+    fn double(&self, p: &Point) -> Point {
+        let s = (&p.x + &p.z).complete().square() % self.n;
+        let d = (&p.x - &p.z).complete().square() % self.n;
+        let t = (&s - &d).complete();
+        let z = (&self.a24 * &t).complete() + &d;
+        Point {
+            x: s * d % self.n,
+            z: t * z % self.n,
+        }
+    }
+
+    // Computes p + q, given p - q.
+    fn add(&self, p: &Point, q: &Point, difference: &Point) -> Point {
+        let u = (&p.x - &p.z).complete() * (&q.x + &q.z).complete() % self.n;
+        let v = (&p.x + &p.z).complete() * (&q.x - &q.z).complete() % self.n;
+        Point {
+            x: (&u + &v).complete().square() * &difference.z % self.n,
+            z: (u - v).square() * &difference.x % self.n,
+        }
+    }
+
+    // Montgomery's ladder.
+    fn mul(&self, p: &Point, k: u64) -> Point {
+        if k == 1 {
+            return p.clone();
+        }
+
+        let mut r0 = p.clone();
+        let mut r1 = self.double(p);
+        for i in (0..k.ilog2()).rev() {
+            if (k >> i) & 1 == 1 {
+                r0 = self.add(&r1, &r0, p);
+                r1 = self.double(&r1);
+            } else {
+                r1 = self.add(&r0, &r1, p);
+                r0 = self.double(&r0);
+            }
+        }
+        r0
+    }
+}
+
+fn nontrivial_gcd(x: &Integer, n: &Integer) -> Option<Integer> {
+    let g = x.gcd_ref(n).complete();
+    if g == 1 || g == *n {
+        None
+    } else {
+        Some(g)
+    }
+}
+
+// Runs the elliptic curve method on a single curve with stage 1 bound b1 and stage 2 bound b2.
+// primes must contain all primes up to b1, and is_prime must mark the primes in (b1, b2] and have
+// length b2 + D + 1.
+fn ecm_one_curve(
+    n: &Integer,
+    sigma: u64,
+    b1: u64,
+    b2: u64,
+    primes: &[u64],
+    is_prime: &[bool],
+) -> Option<Integer> {
+    let (curve, mut q) = match Curve::suyama(n, sigma)? {
+        CurveOrFactor::Curve(curve, q) => (curve, q),
+        CurveOrFactor::Factor(g) => return Some(g),
+    };
+
+    // Stage 1: multiply by all prime powers below b1.
+    for &p in primes.iter().take_while(|&&p| p <= b1) {
+        let mut k = p;
+        while k <= b1 / p {
+            k *= p;
+        }
+        q = curve.mul(&q, k);
+    }
+
+    if let Some(g) = nontrivial_gcd(&q.z, n) {
+        return Some(g);
+    }
+
+    // Stage 2: each prime q in (b1, b2] is written as i * D +- j with j < D / 2. Then q * Q = 0
+    // implies that (i * D) * Q and j * Q have the same x-coordinate, so the cross product of the
+    // projective x-coordinates is accumulated for each prime.
+    let mut baby_steps: Vec<Point> = vec![q.clone(); D as usize / 2];
+    let q2 = curve.double(&q);
+    baby_steps[3] = curve.add(&q2, &q, &q);
+    let mut j = 5;
+    while j < D as usize / 2 {
+        baby_steps[j] = curve.add(&baby_steps[j - 2], &q2, &baby_steps[j - 4]);
+        j += 2;
+    }
+
+    let giant_step = curve.mul(&q, D);
+    let mut i = (b1 / D).max(2);
+    let mut previous = curve.mul(&q, (i - 1) * D);
+    let mut current = curve.mul(&q, i * D);
+    let mut product = Integer::from(1);
+
+    while i * D <= b2 + D / 2 {
+        let mut j = 1;
+        while j < D / 2 {
+            let (lower, upper) = ((i * D - j) as usize, (i * D + j) as usize);
+            if is_prime[lower] || is_prime[upper] {
+                let s = &baby_steps[j as usize];
+                product *= (&current.x * &s.z).complete() - (&s.x * &current.z).complete();
+                product %= n;
+            }
+            j += 2;
+        }
+
+        let next = curve.add(&current, &giant_step, &previous);
+        previous = current;
+        current = next;
+        i += 1;
+    }
+
+    nontrivial_gcd(&product, n)
+}
+
+// Tries up to num_curves random curves with the elliptic curve method. b2 should be about 100 times
+// b1.
+pub fn ecm(n: &Integer, b1: u64, b2: u64, num_curves: usize) -> Option<Integer> {
+    let primes = nt::primes_below(b2 + 1);
+    let mut is_prime = vec![false; (b2 + D + 1) as usize];
+    for &p in primes.iter().skip_while(|&&p| p <= b1) {
+        is_prime[p as usize] = true;
+    }
+    let mut rng = thread_rng();
+
+    for _ in 0..num_curves {
+        let sigma = rng.gen_range(6..1 << 32);
+        if let Some(g) = ecm_one_curve(n, sigma, b1, b2, &primes, &is_prime) {
+            return Some(g);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ecm_finds_small_factor() {
+        // A 40-bit factor of a 140-bit number, the larger factor is 2^100 + 277.
+        let p = Integer::from(1099511627791u64);
+        let q = Integer::from_str_radix("1267650600228229401496703205653", 10).unwrap();
+        let n = (&p * &q).complete();
+        let g = ecm(&n, 2000, 200000, 200).unwrap();
+        assert!(g == p || g == q);
+    }
+}
@@ -0,0 +1,428 @@
+use std::{
+    fmt::{self, Display},
+    io,
+    path::PathBuf,
+};
+
+use log::info;
+use rug::Integer;
+
+use crate::{
+    certificate::{self, Certificate},
+    cofact,
+    driver::{self, PrimeFactorization},
+    ecm,
+    job::Job,
+    nfs::{self, ExternalFiles, Statistics},
+    nt,
+    params::Params,
+    pm1,
+    polynomial::PolynomialPair,
+    rho, siqs,
+    snfs::SpecialForm,
+};
+
+// Composite numbers with more bits than this are split with the number field sieve, smaller ones
+// with the quadratic sieve.
+pub const DEFAULT_NFS_CROSSOVER: u32 = 260;
+
+// Composite divisors of a special form with more bits than this are split with the special number
+// field sieve if the SNFS difficulty is at most SNFS_MAX_DIFFICULTY_RATIO times their size.
+pub const SNFS_CROSSOVER: u32 = 120;
+const SNFS_MAX_DIFFICULTY_RATIO: f64 = 1.5;
+
+// Numbers up to this size are split with Pollard's rho method, trying RHO_CONSTANTS constants with
+// at most RHO_ITERATIONS iterations each, and then with the word-size methods of cofact.
+const RHO_BITS: u32 = 64;
+const RHO_CONSTANTS: u64 = 8;
+const RHO_ITERATIONS: u64 = 1 << 24;
+
+// Stage 1 bound for Pollard's p - 1 method, the stage 2 bound is 100 times as large.
+const PM1_B1: u64 = 20000;
+
+// Levels of ECM effort as (size of the factors searched for in bits, stage 1 bound, number of
+// curves). A level is run if the number to be split has at least three times as many bits.
+const ECM_LEVELS: [(u32, u64, usize); 4] = [
+    (50, 2000, 25),
+    (66, 11000, 90),
+    (83, 50000, 300),
+    (100, 250000, 700),
+];
+
+// Entry point for using the number field sieve as a library. The parameters are chosen based on
+// the size of the input, unless they are fixed with set_params.
+#[derive(Clone, Debug)]
+pub struct Factorizer {
+    params: Option<Params>,
+    nfs_crossover: u32,
+    job_dir: Option<PathBuf>,
+    files: ExternalFiles,
+    polynomial: Option<PolynomialPair>,
+    special_form: Option<SpecialForm>,
+}
+
+// The nontrivial divisors of n found by the number field sieve, together with some statistics
+// about the run. The divisors are sorted and each one is at most sqrt(n).
+#[derive(Clone, Debug)]
+pub struct Factorization {
+    pub n: Integer,
+    pub factors: Vec<Integer>,
+    pub statistics: Statistics,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    // The input can't be handled by the number field sieve, e.g. because it is prime.
+    InvalidInput(String),
+    // All dependencies were processed, but none of them yielded a nontrivial factor.
+    NoFactorFound,
+    // Reading or writing the job directory or one of the external files failed.
+    Io(String),
+}
+
+impl Factorizer {
+    pub fn new() -> Factorizer {
+        Factorizer {
+            params: None,
+            nfs_crossover: DEFAULT_NFS_CROSSOVER,
+            job_dir: None,
+            files: ExternalFiles::default(),
+            polynomial: None,
+            special_form: None,
+        }
+    }
+
+    pub fn set_params(&mut self, params: Params) {
+        self.params = Some(params);
+    }
+
+    // Sets the size in bits above which composites are split with the number field sieve instead
+    // of the quadratic sieve.
+    pub fn set_nfs_crossover(&mut self, bits: u32) {
+        self.nfs_crossover = bits;
+    }
+
+    // Keeps the state of each run of the number field sieve in a subdirectory of dir named after
+    // the number, so that an interrupted factorization continues where it stopped when it is
+    // started again.
+    pub fn set_job_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.job_dir = Some(dir.into());
+    }
+
+    // Reads relations in the format of msieve and CADO-NFS from the file and uses them in addition
+    // to those found by sieving. Relations that don't match the polynomial are skipped.
+    pub fn set_relations_import(&mut self, path: impl Into<PathBuf>) {
+        self.files.import_relations = Some(path.into());
+    }
+
+    // Writes all relations to the file in the format of msieve and CADO-NFS after sieving.
+    pub fn set_relations_export(&mut self, path: impl Into<PathBuf>) {
+        self.files.export_relations = Some(path.into());
+    }
+
+    // Writes the polynomial pair used by the number field sieve to the file, in the .poly format of
+    // msieve and CADO-NFS.
+    pub fn set_polynomial_export(&mut self, path: impl Into<PathBuf>) {
+        self.files.export_polynomial = Some(path.into());
+    }
+
+    // Uses the polynomial pair instead of selecting one when the number field sieve is run on the
+    // number it belongs to, regardless of the crossover size.
+    pub fn set_polynomial(&mut self, pair: PolynomialPair) {
+        self.polynomial = Some(pair);
+    }
+
+    // Uses the special number field sieve for the divisors of the special number, see split.
+    // Numbers of a special form are also recognized without this.
+    pub fn set_special_form(&mut self, form: SpecialForm) {
+        self.special_form = Some(form);
+    }
+
+    // The special form set with set_special_form if n divides it, or else the one of n itself, if
+    // it has one. Smaller numbers than SNFS_CROSSOVER aren't split with the SNFS, so their form is
+    // only recognized if it was set.
+    fn special_form(&self, n: &Integer) -> Option<SpecialForm> {
+        self.special_form
+            .clone()
+            .filter(|form| form.value().is_divisible(n))
+            .or_else(|| {
+                (n.significant_bits() > SNFS_CROSSOVER)
+                    .then(|| SpecialForm::recognize(n))
+                    .flatten()
+            })
+    }
+
+    // Whether the special number field sieve with the form is faster for n than the general one.
+    fn snfs_suited(form: &SpecialForm, n: &Integer) -> bool {
+        form.difficulty() as f64 <= SNFS_MAX_DIFFICULTY_RATIO * n.significant_bits() as f64
+    }
+
+    pub fn params(&self, n: &Integer) -> Params {
+        self.params.unwrap_or_else(|| Params::new(n))
+    }
+
+    pub fn factorize(&self, n: &Integer) -> Result<Factorization, Error> {
+        if *n < 4 {
+            return Err(Error::InvalidInput(format!(
+                "{} has no nontrivial factors",
+                n
+            )));
+        }
+        if nt::bpsw(n) {
+            return Err(Error::InvalidInput(format!("{} is prime", n)));
+        }
+        if n.is_perfect_power() {
+            return Err(Error::InvalidInput(format!("{} is a perfect power", n)));
+        }
+
+        if let Some(pair) = &self.polynomial {
+            if pair.n != *n {
+                return Err(Error::InvalidInput(format!(
+                    "the polynomial belongs to {}",
+                    pair.n
+                )));
+            }
+            pair.check().map_err(Error::InvalidInput)?;
+        }
+
+        // Without a given polynomial, the special number field sieve is used if it is suited for n.
+        let snfs = match &self.polynomial {
+            Some(_) => None,
+            None => self
+                .special_form(n)
+                .filter(|form| Factorizer::snfs_suited(form, n))
+                .and_then(|form| form.polynomial(n).map(|pair| (form, pair))),
+        };
+
+        let io_error = |e: io::Error| Error::Io(e.to_string());
+        let mut params = match (&self.params, &snfs) {
+            (None, Some((form, _))) => Params::new_snfs(form.difficulty()),
+            _ => self.params(n),
+        };
+        let mut job = match &self.job_dir {
+            Some(dir) => Some(Job::open(dir, n).map_err(io_error)?),
+            None => None,
+        };
+
+        // A resumed job continues with its own parameters, except for the number of threads and the
+        // segment size, which don't change the relations found.
+        if let Some(job) = &job {
+            match job.load_params(params).map_err(io_error)? {
+                Some(stored) => {
+                    info!("resuming the job in {}", job.dir().display());
+                    params = Params {
+                        threads: params.threads,
+                        sieve_segment_size: params.sieve_segment_size,
+                        ..stored
+                    };
+                }
+                None => job.save_params(&params).map_err(io_error)?,
+            }
+        }
+
+        let (factors, statistics) = nfs::factorize(
+            n,
+            &params,
+            job.as_mut(),
+            &self.files,
+            self.polynomial
+                .as_ref()
+                .or(snfs.as_ref().map(|(_, pair)| pair)),
+        )
+        .map_err(io_error)?;
+        info!(
+            "sieving took {:?}, linear algebra {:?} and square root {:?}",
+            statistics.sieve_time, statistics.linalg_time, statistics.sqrt_time
+        );
+
+        if factors.is_empty() {
+            return Err(Error::NoFactorFound);
+        }
+
+        Ok(Factorization {
+            n: n.clone(),
+            factors,
+            statistics,
+        })
+    }
+
+    // Computes the complete prime factorization of n, splitting composite parts with the method
+    // most suited for their size.
+    pub fn factor(&self, n: &Integer) -> Result<PrimeFactorization, Error> {
+        driver::factor(n, |m| self.split(m))
+    }
+
+    // Proves that the given numbers are prime with Pocklington's theorem, factoring p - 1 as far as
+    // needed with the same methods as factor. Returns an error if one of them is not a prime.
+    pub fn certify(&self, primes: &[Integer]) -> Result<Certificate, Error> {
+        certificate::certify(primes, |m| self.split(m))
+    }
+
+    // Finds a nontrivial divisor of the odd composite n, which must not be a perfect power. If n
+    // divides a number of special form, its algebraic factors are tried first. Small factors are
+    // searched for with Pollard's rho and p - 1 methods and ECM, then the quadratic or number field
+    // sieve is used, depending on the size of n and whether a polynomial or special form was given
+    // for it. The statistics are returned if the number field sieve was used.
+    fn split(&self, n: &Integer) -> Result<(Integer, Option<Statistics>), Error> {
+        let bits = n.significant_bits();
+
+        // A polynomial given for n means that the number field sieve is wanted.
+        if self.polynomial.as_ref().is_some_and(|pair| pair.n == *n) {
+            return self.split_nfs(n);
+        }
+
+        let form = self.special_form(n);
+        if let Some(form) = &form {
+            for factor in form.algebraic_factors() {
+                let d = factor.gcd(n);
+                if d != 1 && d != *n {
+                    info!("found {} from an algebraic factor of {}", d, form);
+                    return Ok((d, None));
+                }
+            }
+        }
+
+        if bits <= RHO_BITS {
+            if let Some(d) = (1..=RHO_CONSTANTS).find_map(|c| rho::rho(n, c, RHO_ITERATIONS)) {
+                info!("found {} with Pollard's rho method", d);
+                return Ok((d, None));
+            }
+            if let Some(d) = cofact::split(n.to_u64().unwrap()) {
+                info!("found {} with the word-size methods", d);
+                return Ok((Integer::from(d), None));
+            }
+        }
+
+        if let Some(d) = rho::rho(n, 1, 1 << 14) {
+            info!("found {} with Pollard's rho method", d);
+            return Ok((d, None));
+        }
+
+        if let Some(d) = pm1::pm1(n, PM1_B1, 100 * PM1_B1) {
+            info!("found {} with Pollard's p - 1 method", d);
+            return Ok((d, None));
+        }
+
+        for (factor_bits, b1, num_curves) in ECM_LEVELS {
+            if 3 * factor_bits > bits {
+                break;
+            }
+            if let Some(d) = ecm::ecm(n, b1, 100 * b1, num_curves) {
+                info!("found {} with ECM (B1 = {})", d, b1);
+                return Ok((d, None));
+            }
+        }
+
+        if bits > self.nfs_crossover
+            || (bits > SNFS_CROSSOVER && form.is_some_and(|form| Factorizer::snfs_suited(&form, n)))
+        {
+            return self.split_nfs(n);
+        }
+
+        // The quadratic sieve fails only if all dependencies are trivial, which is unlikely to
+        // happen twice.
+        for _ in 0..2 {
+            if let Some(d) = siqs::factorize(n) {
+                info!("found {} with the quadratic sieve", d);
+                return Ok((d, None));
+            }
+        }
+        Err(Error::NoFactorFound)
+    }
+
+    // The given polynomial is only used for the number it belongs to, other numbers reaching the
+    // number field sieve get a polynomial of their own.
+    fn split_nfs(&self, n: &Integer) -> Result<(Integer, Option<Statistics>), Error> {
+        let factorization = match &self.polynomial {
+            Some(pair) if pair.n != *n => Factorizer {
+                polynomial: None,
+                ..self.clone()
+            }
+            .factorize(n)?,
+            _ => self.factorize(n)?,
+        };
+        Ok((
+            factorization.factors[0].clone(),
+            Some(factorization.statistics),
+        ))
+    }
+}
+
+impl Default for Factorizer {
+    fn default() -> Factorizer {
+        Factorizer::new()
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidInput(reason) => write!(f, "invalid input: {}", reason),
+            Error::NoFactorFound => write!(f, "no nontrivial factor found"),
+            Error::Io(e) => write!(f, "i/o error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polynomial;
+
+    #[test]
+    fn factorize_invalid_input() {
+        let factorizer = Factorizer::new();
+        for n in [
+            Integer::from(1),
+            Integer::from(1000000007),
+            Integer::from(998244353).square(),
+        ] {
+            assert!(matches!(
+                factorizer.factorize(&n),
+                Err(Error::InvalidInput(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn factor_multiple_of_polynomial_number() {
+        // The polynomial belongs to p * q. None of the primes is found by the rho or p - 1 methods,
+        // so the number field sieve is run on p * q * r as well and must select its own polynomial.
+        let (p, q, r) = (40001197u32, 40017617u32, 40044461u32);
+        let n = Integer::from(p) * q;
+        let (f, m) = polynomial::select(&n, &Params::new(&n));
+
+        let mut factorizer = Factorizer::new();
+        factorizer.set_nfs_crossover(0);
+        factorizer.set_polynomial(PolynomialPair::new(&n, f, &m));
+        let factorization = factorizer.factor(&(n * r)).unwrap();
+        assert_eq!(
+            factorization.factors,
+            vec![
+                (Integer::from(p), 1),
+                (Integer::from(q), 1),
+                (Integer::from(r), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn recognize_special_forms_above_crossover() {
+        let factorizer = Factorizer::new();
+        // 2^100 + 1 is too small for the SNFS, 2^128 + 1 isn't.
+        assert_eq!(
+            factorizer.special_form(&(Integer::from(1) << 100u32 | 1u32)),
+            None
+        );
+        let form: SpecialForm = "2^128+1".parse().unwrap();
+        assert_eq!(factorizer.special_form(&form.value()), Some(form));
+
+        // A given form is used for small divisors as well.
+        let mut factorizer = Factorizer::new();
+        let form: SpecialForm = "2^100+1".parse().unwrap();
+        factorizer.set_special_form(form.clone());
+        assert_eq!(factorizer.special_form(&form.value()), Some(form));
+    }
+}
@@ -1,34 +1,45 @@
-use core::mem::swap;
-use std::ops::{Index, IndexMut};
+use core::mem::take;
+use std::{
+    borrow::Cow,
+    ops::{Index, IndexMut},
+};
 
-use rug::{integer::IntegerExt64, ops::Pow, Complete, Integer};
+use rug::{integer::IntegerExt64, ops::Pow, Integer};
 
 use crate::{
     nt,
-    params::MAX_DEGREE,
     polynomial::{MpPolynomial, Polynomial},
 };
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+// Stored like MpPolynomial, with a vector that grows as needed.
+#[derive(Clone, Debug)]
 pub struct GfPolynomial {
-    coefficients: [u64; MAX_DEGREE + 1],
+    coefficients: Vec<u64>,
     modulus: u64,
 }
 
 impl GfPolynomial {
     pub fn new(modulus: u64) -> GfPolynomial {
+        GfPolynomial::with_len(1, modulus)
+    }
+
+    // The zero polynomial with room for len coefficients.
+    fn with_len(len: usize, modulus: u64) -> GfPolynomial {
         GfPolynomial {
-            coefficients: [0; MAX_DEGREE + 1],
+            coefficients: vec![0; len.max(1)],
             modulus,
         }
     }
 
     pub fn from_mp_polynomial(f: &MpPolynomial, modulus: u64) -> GfPolynomial {
-        let mut g = GfPolynomial::new(modulus);
-        for i in 0..=MAX_DEGREE {
-            g[i] = f[i].mod_u64(modulus);
+        GfPolynomial {
+            coefficients: f
+                .coefficients_ref()
+                .iter()
+                .map(|c| c.mod_u64(modulus))
+                .collect(),
+            modulus,
         }
-        g
     }
 
     pub fn modulus(&self) -> u64 {
@@ -36,50 +47,96 @@ impl GfPolynomial {
     }
 
     pub fn add(mut self, rhs: &GfPolynomial) -> GfPolynomial {
+        if self.coefficients.len() < rhs.coefficients.len() {
+            self.coefficients.resize(rhs.coefficients.len(), 0);
+        }
         for (i, coefficient) in self.coefficients.iter_mut().enumerate() {
-            *coefficient = (*coefficient + rhs[i]) % self.modulus;
+            *coefficient = nt::mod_add(*coefficient, rhs[i], self.modulus);
         }
         self
     }
 
     // Same routine as in the general polynomial case.
     pub fn mul_mod(&self, f: &GfPolynomial, g: &GfPolynomial) -> GfPolynomial {
-        let d = self.degree();
+        self.mul_mod_with_degree(self.degree(), f, g)
+    }
+
+    // mul_mod with the degree d of self computed by the caller. The loops run over slices of
+    // length d instead of indexing the polynomials.
+    fn mul_mod_with_degree(&self, d: usize, f: &GfPolynomial, g: &GfPolynomial) -> GfPolynomial {
         let p = self.modulus();
+        let (modulus, f, g) = (self.padded(d), f.padded(d), g.padded(d));
 
-        let mut result = GfPolynomial::new(self.modulus);
-        for i in 0..d {
-            result[i] = (g[i] * f[d - 1]) % p;
+        let mut result = GfPolynomial::with_len(d, p);
+        let r = &mut result.coefficients[..d];
+        for (c, g_i) in r.iter_mut().zip(g.iter()) {
+            *c = nt::mod_mul(*g_i, f[d - 1], p);
         }
 
         for i in (0..d - 1).rev() {
-            let mut leading_coefficient = 0u64;
-            for coefficient in result.coefficients_mut().iter_mut().take(d) {
-                swap(&mut leading_coefficient, coefficient);
-            }
+            r.rotate_right(1);
+            let leading_coefficient = take(&mut r[0]);
 
-            for j in 0..d {
-                result[j] += g[j] * f[i];
-                result[j] += p - (leading_coefficient * self[j]) % p;
-                result[j] %= p;
+            for ((c, g_j), modulus_j) in r.iter_mut().zip(g.iter()).zip(modulus.iter()) {
+                *c = nt::mod_add(*c, nt::mod_mul(*g_j, f[i], p), p);
+                *c = nt::mod_sub(*c, nt::mod_mul(leading_coefficient, *modulus_j, p), p);
             }
         }
 
         result
     }
 
+    // The first len coefficients, padded with zeros if fewer are stored.
+    fn padded(&self, len: usize) -> Cow<'_, [u64]> {
+        if self.coefficients.len() >= len {
+            Cow::Borrowed(&self.coefficients[..len])
+        } else {
+            let mut coefficients = self.coefficients.clone();
+            coefficients.resize(len, 0);
+            Cow::Owned(coefficients)
+        }
+    }
+
     fn rem(mut self, modulus: &GfPolynomial) -> GfPolynomial {
         let (d, e) = (self.degree(), modulus.degree());
         let p = self.modulus();
         let modulus_leading_inv = nt::mod_inv(modulus[e], p);
 
         for i in (e..=d).rev() {
-            let quotient = (self[i] * modulus_leading_inv) % p;
+            let quotient = nt::mod_mul(self[i], modulus_leading_inv, p);
+            for j in 0..=e {
+                self[i - e + j] =
+                    nt::mod_sub(self[i - e + j], nt::mod_mul(quotient, modulus[j], p), p);
+            }
+        }
+
+        self
+    }
+
+    // The quotient of the division by the monic polynomial f.
+    fn div(mut self, f: &GfPolynomial) -> GfPolynomial {
+        let (d, e) = (self.degree(), f.degree());
+        let p = self.modulus();
+        let mut quotient = GfPolynomial::new(p);
+
+        for i in (e..=d).rev() {
+            let c = self[i];
+            quotient[i - e] = c;
             for j in 0..=e {
-                self[i - e + j] = (self[j + i - e] + p - (quotient * modulus[j]) % p) % p;
+                self[i - e + j] = nt::mod_sub(self[i - e + j], nt::mod_mul(c, f[j], p), p);
             }
         }
 
+        quotient
+    }
+
+    // Divides by the leading coefficient, since mul_mod expects a monic modulus.
+    fn monic(mut self) -> GfPolynomial {
+        let p = self.modulus();
+        let leading_inv = nt::mod_inv(self[self.degree()], p);
+        for coefficient in self.coefficients_mut() {
+            *coefficient = nt::mod_mul(*coefficient, leading_inv, p);
+        }
         self
     }
 
@@ -92,24 +149,88 @@ impl GfPolynomial {
     }
 
     fn pow_mod(&self, mut f: GfPolynomial, mut e: Integer) -> GfPolynomial {
+        let d = self.degree();
         let mut g = GfPolynomial::new(self.modulus);
         g[0] = 1;
 
         while e != 0 {
             if e.is_odd() {
-                g = self.mul_mod(&g, &f);
+                g = self.mul_mod_with_degree(d, &g, &f);
             }
-            f = self.mul_mod(&f, &f);
+            f = self.mul_mod_with_degree(d, &f, &f);
             e >>= 1;
         }
 
         g
     }
 
+    // Returns the roots in increasing order, each once. The distinct linear factors of f are those
+    // of gcd(x^p - x, f), which are separated by the equal-degree splitting of Cantor and
+    // Zassenhaus. This takes O(log p) multiplications instead of evaluating f at every residue.
+    pub fn roots(&self) -> Vec<u64> {
+        let p = self.modulus();
+        let f = self.clone().monic();
+        if f.degree() == 0 {
+            return if f[0] == 0 {
+                (0..p).collect()
+            } else {
+                Vec::new()
+            };
+        }
+
+        let mut x = GfPolynomial::new(p);
+        x[1] = 1;
+        let mut h = f.pow_mod(x.rem(&f), Integer::from(p));
+        h[1] = nt::mod_sub(h[1], 1, p); // subtract x
+        let g = h.gcd(f.clone()).monic();
+
+        let mut roots: Vec<u64> = Vec::new();
+        g.split_linear(&mut roots);
+        roots.sort_unstable();
+        roots
+    }
+
+    // Collects the roots of self, which must be a monic product of distinct linear factors. For
+    // a = 0, 1, ..., gcd((x + a)^((p - 1) / 2) - 1, self) consists of the factors x - r for which
+    // r + a is a nonzero square, which splits self for about every second a.
+    fn split_linear(self, roots: &mut Vec<u64>) {
+        let p = self.modulus();
+        match self.degree() {
+            0 => return,
+            1 => {
+                roots.push(nt::mod_sub(0, self[0], p));
+                return;
+            }
+            _ if p == 2 => {
+                // The only product of distinct linear factors of degree 2 is x * (x + 1).
+                roots.extend([0, 1]);
+                return;
+            }
+            _ => (),
+        }
+
+        for a in 0..p {
+            let mut y = GfPolynomial::new(p);
+            y[0] = a;
+            y[1] = 1;
+            let mut h = self.pow_mod(y, Integer::from((p - 1) / 2));
+            h[0] = nt::mod_sub(h[0], 1, p);
+            let g = h.gcd(self.clone()).monic();
+            if g.degree() > 0 && g.degree() < self.degree() {
+                let quotient = self.div(&g);
+                g.split_linear(roots);
+                quotient.split_linear(roots);
+                return;
+            }
+        }
+        unreachable!("the linear factors could not be separated");
+    }
+
     // Rabin's test of irreducibility for polynomials over finite fields.
     pub fn is_irreducible(&self) -> bool {
         let d = self.degree();
         let p = self.modulus();
+        let f = self.clone().monic();
 
         let mut prime_divisors: Vec<u64> = Vec::new();
         for q in 2..=d {
@@ -122,16 +243,16 @@ impl GfPolynomial {
         x[1] = 1;
 
         for q in prime_divisors {
-            let mut h = self.pow_mod(x.clone(), Integer::from(p).pow((d as u64 / q) as u32));
-            h[1] = (h[1] + p - 1) % p; // subtract x
-            let g = h.gcd(self.clone());
+            let mut h = f.pow_mod(x.clone(), Integer::from(p).pow((d as u64 / q) as u32));
+            h[1] = nt::mod_sub(h[1], 1, p); // subtract x
+            let g = h.gcd(f.clone());
             if g.degree() != 0 {
                 return false;
             }
         }
 
-        let mut g = self.pow_mod(x.clone(), Integer::from(p).pow(d as u32));
-        g[1] = (g[1] + p - 1) % p;
+        let mut g = f.pow_mod(x.clone(), Integer::from(p).pow(d as u32));
+        g[1] = nt::mod_sub(g[1], 1, p);
 
         g.degree() == 0 && g[0] == 0
     }
@@ -139,36 +260,144 @@ impl GfPolynomial {
 
 impl Polynomial<u64> for GfPolynomial {
     fn degree(&self) -> usize {
-        let mut d = MAX_DEGREE;
-        while d > 0 && self[d] == 0 {
-            d -= 1;
-        }
-        d
+        self.coefficients.iter().rposition(|c| *c != 0).unwrap_or(0)
     }
 
-    fn coefficients(self) -> [u64; MAX_DEGREE + 1] {
+    fn coefficients(self) -> Vec<u64> {
         self.coefficients
     }
 
-    fn coefficients_ref(&self) -> &[u64; MAX_DEGREE + 1] {
+    fn coefficients_ref(&self) -> &[u64] {
         &self.coefficients
     }
 
-    fn coefficients_mut(&mut self) -> &mut [u64; MAX_DEGREE + 1] {
+    fn coefficients_mut(&mut self) -> &mut [u64] {
         &mut self.coefficients
     }
 }
 
+impl PartialEq for GfPolynomial {
+    fn eq(&self, other: &GfPolynomial) -> bool {
+        let d = self.degree();
+        self.modulus == other.modulus
+            && d == other.degree()
+            && self.coefficients[..=d] == other.coefficients[..=d]
+    }
+}
+
+impl Eq for GfPolynomial {}
+
 impl Index<usize> for GfPolynomial {
     type Output = u64;
 
     fn index(&self, i: usize) -> &u64 {
-        &self.coefficients[i]
+        self.coefficients.get(i).unwrap_or(&0)
     }
 }
 
 impl IndexMut<usize> for GfPolynomial {
     fn index_mut(&mut self, i: usize) -> &mut u64 {
+        if i >= self.coefficients.len() {
+            self.coefficients.resize(i + 1, 0);
+        }
         &mut self.coefficients[i]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn irreducible_non_monic() {
+        // Modulo 7, 4 * x^3 + 1 = 4 * (x^3 + 2) is irreducible since -2 is no cube, while
+        // 4 * x^3 + 3 = 4 * (x - 2) * (x^2 + 2 * x + 4). Modulo 5, 2 * x^2 + 1 is irreducible and
+        // 2 * x^2 + 3 = 2 * (x - 1) * (x + 1).
+        for (coefficients, p, irreducible) in [
+            ([1, 0, 0, 4], 7, true),
+            ([3, 0, 0, 4], 7, false),
+            ([1, 0, 2, 0], 5, true),
+            ([3, 0, 2, 0], 5, false),
+        ] {
+            let mut f = GfPolynomial::new(p);
+            for (i, c) in coefficients.into_iter().enumerate() {
+                f[i] = c;
+            }
+            assert_eq!(
+                f.is_irreducible(),
+                irreducible,
+                "{:?} modulo {}",
+                coefficients,
+                p
+            );
+        }
+    }
+
+    fn brute_force_roots(f: &MpPolynomial, p: u64) -> Vec<u64> {
+        (0..p)
+            .filter(|x| f.evaluate(*x).is_divisible_u64(p))
+            .collect()
+    }
+
+    #[test]
+    fn roots_mod_p() {
+        // 2 * x^4 + 3 * x^3 - x^2 + 7 * x + 5, with p dividing neither the leading coefficient
+        // nor the discriminant, and x^3 - x with all of 0, 1 and -1 as roots.
+        let mut f = MpPolynomial::new();
+        for (i, c) in [5, 7, -1, 3, 2].into_iter().enumerate() {
+            f[i] = Integer::from(c);
+        }
+        let mut g = MpPolynomial::new();
+        g[3] = Integer::from(1);
+        g[1] = Integer::from(-1);
+        for p in nt::primes_below(400) {
+            assert_eq!(f.find_roots_mod_p(p), brute_force_roots(&f, p), "p = {}", p);
+            assert_eq!(g.find_roots_mod_p(p), brute_force_roots(&g, p), "p = {}", p);
+        }
+
+        // (x - 1)^2 * (x - 3) * (x^2 + 1) has the roots 1 and 3 modulo 7 and 1, 2 and 3 modulo 5,
+        // with 1 as a double root.
+        let mut h = MpPolynomial::new();
+        for (i, c) in [-3, 7, -8, 8, -5, 1].into_iter().enumerate() {
+            h[i] = Integer::from(c);
+        }
+        assert_eq!(h.find_roots_mod_p(7), vec![1, 3]);
+        assert_eq!(h.find_roots_mod_p(5), vec![1, 2, 3]);
+
+        // (x^2 + x + 1) * (x - 12345) * (x - 999999) modulo a prime near 2^31, which is 1 modulo 3,
+        // so that there are two cube roots of unity as well.
+        let p = 2147483647;
+        let mut k = MpPolynomial::new();
+        k[2] = Integer::from(1);
+        k[1] = Integer::from(1);
+        k[0] = Integer::from(1);
+        for r in [12345, 999999] {
+            let mut product = MpPolynomial::new();
+            for i in 0..=k.degree() {
+                product[i + 1] += &k[i];
+                product[i] -= Integer::from(r) * &k[i];
+            }
+            k = product;
+        }
+        let roots = k.find_roots_mod_p(p);
+        assert_eq!(roots.len(), 4);
+        assert!(roots.contains(&12345) && roots.contains(&999999));
+        for r in roots {
+            assert!(k.evaluate(r).is_divisible_u64(p));
+        }
+
+        // (x^2 + x + 1) * (x - 12345) * (x + 7) modulo the largest prime below 2^64, which is 2
+        // modulo 3, so that x^2 + x + 1 is irreducible.
+        let p = u64::MAX - 58;
+        let mut l = MpPolynomial::new();
+        for (i, c) in [-86415, -98753, -98752, -12337, 1].into_iter().enumerate() {
+            l[i] = Integer::from(c);
+        }
+        assert_eq!(l.find_roots_mod_p(p), vec![12345, p - 7]);
+        let mut m = MpPolynomial::new();
+        for (i, c) in [1, 1, 1].into_iter().enumerate() {
+            m[i] = Integer::from(c);
+        }
+        assert!(GfPolynomial::from_mp_polynomial(&m, p).is_irreducible());
+    }
+}
@@ -0,0 +1,225 @@
+use rug::{
+    integer::IntegerExt64,
+    ops::{Pow, RemRounding},
+    Complete, Integer,
+};
+
+use crate::{
+    gfpolynomial::GfPolynomial,
+    nt,
+    polynomial::{MpPolynomial, Polynomial},
+    polyselect,
+};
+
+// The p-adic roots at bad ideals are computed modulo the smallest power of p above
+// 2^VALUATION_BITS, which determines the valuations of all norms below that.
+const VALUATION_BITS: u32 = 256;
+
+// The polynomial x^d * f(1 / x), whose roots near 0 are those of f near infinity.
+fn reversed(f: &MpPolynomial) -> MpPolynomial {
+    let d = f.degree();
+    let mut g = MpPolynomial::new();
+    for i in 0..=d {
+        g[d - i] = f[i].clone();
+    }
+    g
+}
+
+// Whether the prime ideal (p, r) of degree one is bad, i.e. r is a multiple root of f modulo p, or
+// the reversed polynomial has a multiple root at 0 for the projective root r = p.
+pub fn is_bad(f: &MpPolynomial, p: u64, r: u64) -> bool {
+    let (f, r) = match r == p {
+        true => (reversed(f), 0),
+        false => (f.clone(), r),
+    };
+    f.evaluate(r).is_divisible_u64(p) && f.derivative().evaluate(r).is_divisible_u64(p)
+}
+
+// Divides the coefficients by the largest power of p dividing all of them.
+fn primitive_part(mut f: MpPolynomial, p: u64) -> MpPolynomial {
+    while f.coefficients_ref().iter().all(|c| c.is_divisible_u64(p))
+        && f.coefficients_ref().iter().any(|c| *c != 0)
+    {
+        for c in f.coefficients_mut() {
+            *c /= p;
+        }
+    }
+    f
+}
+
+// The multiplicity of the root t of f modulo p.
+fn multiplicity(f: &MpPolynomial, p: u64, t: u64) -> usize {
+    let g = polyselect::translate(f, &Integer::from(t));
+    g.coefficients_ref()
+        .iter()
+        .position(|c| !c.is_divisible_u64(p))
+        .unwrap()
+}
+
+// Collects the roots of f in the p-adic integers that are congruent to c modulo p^j, modulo
+// p^precision, given that there are m roots congruent to c with multiplicity. Returns the number of
+// the others, which lie in extensions of the p-adic numbers or can't be told apart at this
+// precision. The disk around c is refined until each root of f(c + p^j * t) modulo p is simple.
+fn p_adic_roots(
+    f: &MpPolynomial,
+    p: u64,
+    c: &Integer,
+    j: u32,
+    m: usize,
+    precision: u32,
+    roots: &mut Vec<Integer>,
+) -> usize {
+    let scale = Integer::from(p).pow(j);
+    let mut h = polyselect::translate(f, c);
+    let mut power = Integer::from(1);
+    for coefficient in h.coefficients_mut() {
+        *coefficient *= &power;
+        power *= &scale;
+    }
+    let h = primitive_part(h, p);
+
+    // The roots t of h with nonnegative valuation are those of its reduction.
+    let mut others = m;
+    for t in GfPolynomial::from_mp_polynomial(&h, p).roots() {
+        let multiplicity = multiplicity(&h, p, t);
+        if multiplicity == 1 {
+            let modulus = Integer::from(p).pow(precision);
+            roots.push(
+                (nt::hensel_lift(&h, p, t, precision - j).unwrap() * &scale + c).rem_euc(&modulus),
+            );
+            others -= 1;
+        } else if j + 1 < precision {
+            let center = Integer::from(&scale * t) + c;
+            others -= multiplicity;
+            others += p_adic_roots(f, p, &center, j + 1, multiplicity, precision, roots);
+        }
+    }
+    others
+}
+
+// A bad prime ideal (p, r) of degree one, see is_bad. Several prime ideals of the number field may
+// lie above it, and the valuation of the norm of a + b * alpha at p doesn't tell how it is
+// distributed among them. They are told apart by the roots rho of f in the p-adic integers that
+// are congruent to r: the valuation belonging to rho is that of x - rho, where x = -a / b. What
+// remains of the valuation belongs to the roots in extensions of the p-adic numbers, which are
+// kept together. For the projective root, the same holds for the reversed polynomial and
+// x = -b / a.
+pub struct BadIdeal {
+    pub p: u64,
+    pub r: u64,
+    precision: u32,
+    modulus: Integer,
+    roots: Vec<Integer>,
+    rest: bool,
+}
+
+impl BadIdeal {
+    // Returns None if (p, r) is not bad.
+    pub fn new(f: &MpPolynomial, p: u64, r: u64) -> Option<BadIdeal> {
+        if !is_bad(f, p, r) {
+            return None;
+        }
+        let (f, root) = match r == p {
+            true => (reversed(f), 0),
+            false => (f.clone(), r),
+        };
+        let precision = VALUATION_BITS / p.ilog2() + 1;
+        let mut roots: Vec<Integer> = Vec::new();
+        let m = multiplicity(&f, p, root);
+        let others = p_adic_roots(&f, p, &Integer::from(root), 1, m, precision, &mut roots);
+        roots.sort();
+
+        Some(BadIdeal {
+            p,
+            r,
+            precision,
+            modulus: Integer::from(p).pow(precision),
+            roots,
+            rest: others > 0,
+        })
+    }
+
+    // The number of ideals the valuations are split into: one for each p-adic root and one for the
+    // rest, if there is any.
+    pub fn len(&self) -> usize {
+        self.roots.len() + self.rest as usize
+    }
+
+    // The valuations of a + b * alpha at the ideals above (p, r), given the valuation e of its norm
+    // at p. Returns None if they can't be determined at the precision of the roots.
+    pub fn valuations(&self, a: i64, b: u64, e: u32) -> Option<Vec<u32>> {
+        let (u, w) = match self.r == self.p {
+            true => (Integer::from(b), Integer::from(a)),
+            false => (Integer::from(a), Integer::from(b)),
+        };
+        let x = (-u * w.invert(&self.modulus).ok()?).rem_euc(&self.modulus);
+
+        let mut valuations: Vec<u32> = Vec::with_capacity(self.len());
+        let mut rest = e;
+        for rho in &self.roots {
+            let mut difference = (&x - rho).complete().rem_euc(&self.modulus);
+            if difference == 0 {
+                return None;
+            }
+            let v = difference.remove_factor_mut(&Integer::from(self.p));
+            debug_assert!(v < self.precision);
+            rest = rest.checked_sub(v)?;
+            valuations.push(v);
+        }
+        match self.rest {
+            true => valuations.push(rest),
+            false if rest != 0 => return None,
+            false => (),
+        }
+        Some(valuations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nfs;
+
+    fn polynomial(coefficients: &[i64]) -> MpPolynomial {
+        let mut f = MpPolynomial::new();
+        for (i, c) in coefficients.iter().enumerate() {
+            f[i] = Integer::from(*c);
+        }
+        f
+    }
+
+    #[test]
+    fn bad_ideal_valuations() {
+        // x^2 - 17 = (x - 1)^2 modulo 2, but 17 is a square in the 2-adic integers, with the roots
+        // 7 and 9 modulo 16. So there are two ideals above (2, 1), and (-1, 1), i.e. sqrt(17) - 1
+        // with the norm -16, has the valuations v(1 - 7) = 1 and v(1 - 9) = 3 at them.
+        let f = polynomial(&[-17, 0, 1]);
+        assert!(is_bad(&f, 2, 1));
+        assert!(!is_bad(&f, 13, 5));
+        let ideal = BadIdeal::new(&f, 2, 1).unwrap();
+        assert_eq!(ideal.len(), 2);
+        assert_eq!(ideal.valuations(-1, 1, 4), Some(vec![1, 3]));
+        assert_eq!(ideal.valuations(-1, 1, 5), None);
+
+        // x^2 + 9 = x^2 modulo 3, and its roots 3i and -3i are not 3-adic, so all of the valuation
+        // belongs to one ideal above (3, 0), which divides the pairs with 3 | a.
+        let f = polynomial(&[9, 0, 1]);
+        let ideal = BadIdeal::new(&f, 3, 0).unwrap();
+        assert_eq!(ideal.len(), 1);
+        for (a, b) in [(3, 1), (6, 5), (27, 2)] {
+            let e = nfs::norm(&f, a, b).remove_factor(&Integer::from(3)).1;
+            assert_eq!(ideal.valuations(a, b, e), Some(vec![e]));
+        }
+
+        // 12 * x^2 + x - 1 has the projective root modulo 2, which is simple since the reversed
+        // polynomial -x^2 + x + 12 = x * (x + 1) modulo 2. For 4 * x^2 + 1, the reversed polynomial
+        // x^2 + 4 has a double root at 0 modulo 2, but its roots 2i and -2i are not 2-adic. The
+        // norm of 1 + 2 * alpha is 8.
+        let f = polynomial(&[-1, 1, 12]);
+        assert!(!is_bad(&f, 2, 2));
+        let f = polynomial(&[1, 0, 4]);
+        let ideal = BadIdeal::new(&f, 2, 2).unwrap();
+        assert_eq!(ideal.len(), 1);
+        assert_eq!(ideal.valuations(1, 2, 3), Some(vec![3]));
+    }
+}
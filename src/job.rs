@@ -0,0 +1,236 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use rug::Integer;
+
+use crate::{params::Params, polynomial::PolynomialPair};
+
+const PARAMS_FILE: &str = "params";
+const POLYNOMIAL_FILE: &str = "polynomial.poly";
+const RELATIONS_FILE: &str = "relations";
+
+// Lines of this form follow the relations of each completed special-q in the relations file.
+const SPECIAL_Q_DONE: &str = "# done with special-q ";
+
+// The contents of the relations file of a job.
+pub struct StoredRelations {
+    pub relations: Vec<(i64, u64)>,
+    pub completed_special_q: Vec<(u64, u64)>,
+}
+
+// The directory in which the number field sieve keeps the state of the factorization of one number:
+// the parameters, the polynomial and the relations found so far. The relations are appended after
+// each special-q, so an interrupted run can be resumed where it stopped.
+pub struct Job {
+    dir: PathBuf,
+    relations: Option<BufWriter<File>>,
+}
+
+// Prefixes the message of an error with the path of the file it concerns.
+pub fn with_path(path: &Path) -> impl Fn(io::Error) -> io::Error + '_ {
+    move |e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
+
+fn invalid_data(path: &Path, message: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{}: {}", path.display(), message),
+    )
+}
+
+// Reads the file if it exists.
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(s) => Ok(Some(s)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(with_path(path)(e)),
+    }
+}
+
+impl Job {
+    // Opens the job for n, which is the subdirectory of root named after n. It is created if it
+    // doesn't exist yet.
+    pub fn open(root: &Path, n: &Integer) -> io::Result<Job> {
+        let dir = root.join(n.to_string());
+        fs::create_dir_all(&dir).map_err(with_path(&dir))?;
+        Ok(Job {
+            dir,
+            relations: None,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Parameters that are missing in the file (e.g. because they were added later) are taken from
+    // defaults, which should be the parameters a new job for the same number would be started with.
+    pub fn load_params(&self, defaults: Params) -> io::Result<Option<Params>> {
+        let path = self.dir.join(PARAMS_FILE);
+        match read_optional(&path)? {
+            Some(s) => {
+                let mut params = defaults;
+                params
+                    .apply_assignments(&s)
+                    .map_err(|e| invalid_data(&path, e))?;
+                Ok(Some(params))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn save_params(&self, params: &Params) -> io::Result<()> {
+        let path = self.dir.join(PARAMS_FILE);
+        fs::write(&path, params.to_string()).map_err(with_path(&path))
+    }
+
+    // Loads the polynomial pair, which is stored in the .poly format of msieve and CADO-NFS.
+    pub fn load_polynomial(&self) -> io::Result<Option<PolynomialPair>> {
+        let path = self.dir.join(POLYNOMIAL_FILE);
+        match read_optional(&path)? {
+            Some(s) => Ok(Some(s.parse().map_err(|e| invalid_data(&path, e))?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save_polynomial(&self, pair: &PolynomialPair) -> io::Result<()> {
+        let path = self.dir.join(POLYNOMIAL_FILE);
+        fs::write(&path, pair.to_string()).map_err(with_path(&path))
+    }
+
+    // Reads the relations file and returns the completed special-q and their relations (a, b).
+    // Anything written after the last completed special-q is discarded, and the file is opened for
+    // appending further relations.
+    pub fn resume(&mut self) -> io::Result<StoredRelations> {
+        let path = self.dir.join(RELATIONS_FILE);
+        let s = read_optional(&path)?.unwrap_or_default();
+
+        let mut relations: Vec<(i64, u64)> = Vec::new();
+        let mut completed_relations = 0;
+        let mut completed_special_q: Vec<(u64, u64)> = Vec::new();
+        let mut completed_len = 0;
+        let mut offset = 0;
+
+        for line in s.split_inclusive('\n') {
+            offset += line.len();
+            if !line.ends_with('\n') {
+                break;
+            }
+            let line = line.trim();
+            if let Some(q) = line.strip_prefix(SPECIAL_Q_DONE.trim()) {
+                let q = parse_pair(q.trim())
+                    .ok_or_else(|| invalid_data(&path, format!("invalid line {}", line)))?;
+                completed_special_q.push((q.0 as u64, q.1));
+                completed_relations = relations.len();
+                completed_len = offset;
+            } else if !line.is_empty() && !line.starts_with('#') {
+                relations.push(
+                    parse_pair(line)
+                        .ok_or_else(|| invalid_data(&path, format!("invalid line {}", line)))?,
+                );
+            }
+        }
+        relations.truncate(completed_relations);
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(with_path(&path))?;
+        file.set_len(completed_len as u64)
+            .map_err(with_path(&path))?;
+        self.relations = Some(BufWriter::new(file));
+
+        Ok(StoredRelations {
+            relations,
+            completed_special_q,
+        })
+    }
+
+    // Appends the relations found for the special-q (q, s) and marks it as completed.
+    pub fn append_relations(
+        &mut self,
+        (q, s): (u64, u64),
+        relations: &[(i64, u64)],
+    ) -> io::Result<()> {
+        if self.relations.is_none() {
+            self.resume()?;
+        }
+        let writer = self.relations.as_mut().unwrap();
+        let mut write = || -> io::Result<()> {
+            for (a, b) in relations {
+                writeln!(writer, "{},{}", a, b)?;
+            }
+            writeln!(writer, "{}{},{}", SPECIAL_Q_DONE, q, s)?;
+            writer.flush()
+        };
+        write().map_err(with_path(&self.dir.join(RELATIONS_FILE)))
+    }
+}
+
+fn parse_pair(s: &str) -> Option<(i64, u64)> {
+    let (a, b) = s.split_once(',')?;
+    Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resume_relations() {
+        let root = std::env::temp_dir().join(format!("nfs-job-test-{}", std::process::id()));
+        let n = Integer::from(1000003u64 * 1000033);
+
+        let mut job = Job::open(&root, &n).unwrap();
+        job.append_relations((101, 7), &[(-5, 1), (3, 2)]).unwrap();
+        job.append_relations((103, 11), &[]).unwrap();
+        job.append_relations((107, 13), &[(17, 4)]).unwrap();
+        drop(job);
+
+        // A run interrupted in the middle of a special-q.
+        let path = root.join(n.to_string()).join(RELATIONS_FILE);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "8,3\n9,").unwrap();
+        drop(file);
+
+        let mut job = Job::open(&root, &n).unwrap();
+        let stored = job.resume().unwrap();
+        assert_eq!(stored.relations, vec![(-5, 1), (3, 2), (17, 4)]);
+        assert_eq!(
+            stored.completed_special_q,
+            vec![(101, 7), (103, 11), (107, 13)]
+        );
+
+        job.append_relations((109, 2), &[(1, 1)]).unwrap();
+        let stored = Job::open(&root, &n).unwrap().resume().unwrap();
+        assert_eq!(stored.relations, vec![(-5, 1), (3, 2), (17, 4), (1, 1)]);
+        assert_eq!(stored.completed_special_q.len(), 4);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn load_params_with_defaults() {
+        let root = std::env::temp_dir().join(format!("nfs-params-test-{}", std::process::id()));
+        let n = Integer::from(1000003u64 * 1000033);
+        let job = Job::open(&root, &n).unwrap();
+        let defaults = Params::new_snfs(256);
+        assert_eq!(job.load_params(defaults).unwrap(), None);
+
+        // A file written before lattice_rows existed.
+        let mut stored = Params::new(&n);
+        stored.rational_fudge = 3;
+        let s = stored.to_string().replace("lattice_rows", "# lattice_rows");
+        fs::write(root.join(n.to_string()).join(PARAMS_FILE), s).unwrap();
+        let params = job.load_params(defaults).unwrap().unwrap();
+        assert_eq!(params.rational_fudge, 3);
+        assert_eq!(params.rational_base_size, stored.rational_base_size);
+        assert_eq!(params.lattice_rows, defaults.lattice_rows);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,370 @@
+use crate::{nt, params::Params, polynomial::MpPolynomial};
+
+pub fn ilog2_rounded(x: u64) -> u32 {
+    ((x * x).ilog2() + 1) >> 1
+}
+
+// The initial value of the rational sieve array for a row with |a| <= a_max and |b| <= b_max: the
+// fudge minus the size in bits of the largest norm Y1 * a - Y0 * b. A wide Y0 can make the norm
+// longer than 127 bits, so this is computed in i32 and clamped to the range of i8.
+fn rational_threshold(a_max: u64, b_max: u64, g: &MpPolynomial, fudge: i8) -> i8 {
+    let bits = (ilog2_rounded(a_max) + g[1].significant_bits())
+        .max(ilog2_rounded(b_max) + g[0].significant_bits());
+    (fudge as i32 - bits as i32).clamp(i8::MIN as i32, i8::MAX as i32) as i8
+}
+
+// The hits of the large primes in one segment of the sieved region, as the position in the segment
+// and log2(p).
+type Bucket = Vec<(u32, i8)>;
+
+// Adds log2(p) to all positions k of row with i + j * r = 0 mod p, where i = i_start + k. row is a
+// part of the row j of the region.
+fn line_sieve(j: u64, i_start: i64, row: &mut [i8], base: &[(u64, u64)]) {
+    for (p, r) in base {
+        if !j.is_multiple_of(*p) {
+            let log2p = ilog2_rounded(*p) as i8;
+            let jr = nt::mod_mul(j, *r, *p);
+            let mut k = (-(jr as i64) - i_start).rem_euclid(*p as i64) as usize;
+            while k < row.len() {
+                row[k] += log2p;
+                k += *p as usize;
+            }
+        }
+    }
+}
+
+// Sorts the hits of the primes of base in the rows 1 to rows of length row_len into one bucket per
+// segment of the region. The first hit in row j + 1 is that of row j moved by -r modulo p, so
+// moving to the next row only costs a subtraction. This pays off for primes above the row length,
+// which hit a row at most once.
+fn fill_buckets(
+    base: &[(u64, u64)],
+    row_len: usize,
+    rows: usize,
+    segment_size: usize,
+    buckets: &mut [Bucket],
+) {
+    let i0 = -(row_len as i64 / 2);
+    for bucket in buckets.iter_mut() {
+        bucket.clear();
+    }
+    for &(p, r) in base {
+        let log2p = ilog2_rounded(p) as i8;
+        let mut k = (-(r as i64) - i0).rem_euclid(p as i64) as u64;
+        for j in 1..=rows {
+            // Pairs with p | i and p | j are not coprime.
+            if p > rows as u64 || !(j as u64).is_multiple_of(p) {
+                let mut position = (j - 1) * row_len + k as usize;
+                while position < j * row_len {
+                    buckets[position / segment_size]
+                        .push(((position % segment_size) as u32, log2p));
+                    position += p as usize;
+                }
+            }
+            k = if k >= r { k - r } else { k + p - r };
+        }
+    }
+}
+
+// Sieves the positions start to start + segment.len() of the region, which consists of rows of
+// length row_len. Each row is initialized with init(j), sieved directly with the primes of
+// small_base and then with the hits of the larger primes in bucket.
+fn sieve_segment(
+    segment: &mut [i8],
+    start: usize,
+    row_len: usize,
+    small_base: &[(u64, u64)],
+    bucket: &Bucket,
+    init: impl Fn(usize) -> i8,
+) {
+    let i0 = -(row_len as i64 / 2);
+    let end = start + segment.len();
+    let mut position = start;
+    while position < end {
+        let j = position / row_len + 1;
+        let row_end = (j * row_len).min(end);
+        let row = &mut segment[position - start..row_end - start];
+        row.fill(init(j));
+        line_sieve(j as u64, i0 + (position % row_len) as i64, row, small_base);
+        position = row_end;
+    }
+    for &(k, log2p) in bucket {
+        segment[k as usize] += log2p;
+    }
+}
+
+// Computes a reduced basis of the lattice of all (a, b) with a + b * s = 0 mod q, using Gauss'
+// algorithm. The norm is a^2 + (skew * b)^2, so that |a| is about skew times |b| in the sieved
+// region, matching a polynomial of that skewness. The first vector is the shorter one.
+pub fn reduce_lattice(q: u64, s: u64, skew: u64) -> [(i64, i64); 2] {
+    let skew = skew as i128;
+    let norm = |(a, b): (i128, i128)| a * a + (skew * b) * (skew * b);
+    let mut u: (i128, i128) = (q as i128, 0);
+    let mut v: (i128, i128) = (-(s as i128), 1);
+
+    loop {
+        if norm(u) < norm(v) {
+            std::mem::swap(&mut u, &mut v);
+        }
+        // Subtract the multiple of v closest to the projection of u onto v.
+        let dot = u.0 * v.0 + skew * skew * u.1 * v.1;
+        let k = (2 * dot + norm(v)).div_euclid(2 * norm(v));
+        u = (u.0 - k * v.0, u.1 - k * v.1);
+        if norm(u) >= norm(v) {
+            break;
+        }
+    }
+
+    [(v.0 as i64, v.1 as i64), (u.0 as i64, u.1 as i64)]
+}
+
+// Expresses the condition a + b * r = 0 mod p (or b = 0 mod p for the projective root r = p) for
+// (a, b) = i * u + j * v in the form i + j * r' = 0 mod p, so that the rows of the lattice can be
+// line sieved with the roots r'. Primes for which this is not possible are left out.
+fn lattice_base(base: &[(u64, u64)], [u, v]: [(i64, i64); 2]) -> Vec<(u64, u64)> {
+    let mut transformed: Vec<(u64, u64)> = Vec::with_capacity(base.len());
+
+    for &(p, r) in base {
+        let residue = |(a, b): (i64, i64)| {
+            if r == p {
+                b.rem_euclid(p as i64) as u64
+            } else {
+                (a as i128 + b as i128 * r as i128).rem_euclid(p as i128) as u64
+            }
+        };
+        let (x, y) = (residue(u), residue(v));
+        if x != 0 {
+            transformed.push((p, nt::mod_mul(y, nt::mod_inv(x, p), p)));
+        }
+    }
+
+    transformed
+}
+
+// Owns the sieve arrays and buckets, so that each thread can sieve with its own.
+pub struct LatticeSiever {
+    skew: u64,
+    rational_sieve_array: Vec<i8>,
+    algebraic_sieve_array: Vec<i8>,
+    rational_buckets: Vec<Bucket>,
+    algebraic_buckets: Vec<Bucket>,
+}
+
+impl LatticeSiever {
+    // The lattices are reduced with respect to the given skewness, see reduce_lattice.
+    pub fn new(params: &Params, skew: u64) -> LatticeSiever {
+        let segment_size = LatticeSiever::segment_size(params);
+        LatticeSiever {
+            skew,
+            rational_sieve_array: vec![0; segment_size],
+            algebraic_sieve_array: vec![0; segment_size],
+            rational_buckets: Vec::new(),
+            algebraic_buckets: Vec::new(),
+        }
+    }
+
+    fn segment_size(params: &Params) -> usize {
+        params
+            .sieve_segment_size
+            .min(params.sieve_array_size * params.lattice_rows)
+    }
+
+    // Sieves the sublattice of pairs (a, b) with a + b * s = 0 mod q, where (q, s) is a prime ideal
+    // of the algebraic factor base. The region sieved consists of the points i * u + j * v for a
+    // reduced basis (u, v), with -sieve_array_size / 2 <= i < sieve_array_size / 2 and
+    // 1 <= j <= lattice_rows. Returns the coprime pairs (a, b) with b > 0 whose norms are likely
+    // smooth on both sides.
+    //
+    // The rows are laid out one after the other and sieved in segments of sieve_segment_size, so
+    // that the sieve arrays stay in the cache. Primes below the row length sieve each row directly,
+    // the hits of the larger ones are collected in buckets for the whole region first.
+    pub fn sieve(
+        &mut self,
+        q: u64,
+        s: u64,
+        g: &MpPolynomial,
+        rational_base: &[(u64, u64)],
+        algebraic_base: &[(u64, u64)],
+        params: &Params,
+    ) -> Vec<(i64, u64)> {
+        let basis = reduce_lattice(q, s, self.skew);
+        let [u, v] = basis;
+        let rational_base = lattice_base(rational_base, basis);
+        let algebraic_base = lattice_base(algebraic_base, basis);
+
+        let row_len = params.sieve_array_size;
+        let region = row_len * params.lattice_rows;
+        let segment_size = LatticeSiever::segment_size(params);
+        let segments = region.div_ceil(segment_size);
+        let small_rational = rational_base.partition_point(|&(p, _)| p < row_len as u64);
+        let small_algebraic = algebraic_base.partition_point(|&(p, _)| p < row_len as u64);
+        self.rational_buckets.resize_with(segments, Vec::new);
+        self.algebraic_buckets.resize_with(segments, Vec::new);
+        fill_buckets(
+            &rational_base[small_rational..],
+            row_len,
+            params.lattice_rows,
+            segment_size,
+            &mut self.rational_buckets,
+        );
+        fill_buckets(
+            &algebraic_base[small_algebraic..],
+            row_len,
+            params.lattice_rows,
+            segment_size,
+            &mut self.algebraic_buckets,
+        );
+
+        // The norm on the rational side is Y1 * a - Y0 * b, so it is estimated with the largest a
+        // and b in the row. On the algebraic side, q is known to divide the norm.
+        let i0 = -(row_len as i64 / 2);
+        let rational_init = |j: usize| {
+            let j = j as i64;
+            let a_max = (i0.unsigned_abs() * u.0.unsigned_abs() + (j * v.0).unsigned_abs()).max(2);
+            let b_max = (i0.unsigned_abs() * u.1.unsigned_abs() + (j * v.1).unsigned_abs()).max(2);
+            rational_threshold(a_max, b_max, g, params.rational_fudge)
+        };
+        let algebraic_init = |_| -params.algebraic_threshold + ilog2_rounded(q) as i8;
+
+        let mut candidates: Vec<(i64, u64)> = Vec::new();
+        for k in 0..segments {
+            let start = k * segment_size;
+            let len = segment_size.min(region - start);
+            let rational = &mut self.rational_sieve_array[..len];
+            let algebraic = &mut self.algebraic_sieve_array[..len];
+            sieve_segment(
+                rational,
+                start,
+                row_len,
+                &rational_base[..small_rational],
+                &self.rational_buckets[k],
+                rational_init,
+            );
+            sieve_segment(
+                algebraic,
+                start,
+                row_len,
+                &algebraic_base[..small_algebraic],
+                &self.algebraic_buckets[k],
+                algebraic_init,
+            );
+
+            for x in 0..len {
+                if rational[x] >= 0 && algebraic[x] >= 0 {
+                    let position = start + x;
+                    let (i, j) = (
+                        i0 + (position % row_len) as i64,
+                        (position / row_len + 1) as i64,
+                    );
+                    let (mut a, mut b) = (i * u.0 + j * v.0, i * u.1 + j * v.1);
+                    if b < 0 {
+                        (a, b) = (-a, -b);
+                    }
+                    if b == 0 || a == 0 || nt::gcd(a.unsigned_abs(), b as u64) != 1 {
+                        continue;
+                    }
+                    candidates.push((a, b as u64));
+                }
+            }
+        }
+
+        candidates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primes::Primes;
+    use rug::Integer;
+
+    #[test]
+    fn reduced_lattice_basis() {
+        for (q, s, skew) in [
+            (10007, 5, 1),
+            (10007, 3456, 1),
+            (65537, 65536, 1),
+            (1000003, 123457, 1),
+            (1000003, 123457, 40),
+        ] {
+            let [u, v] = reduce_lattice(q, s, skew);
+            let norm = |(a, b): (i64, i64)| (a as i128).pow(2) + (skew as i128 * b as i128).pow(2);
+
+            // Both vectors lie in the lattice, which has determinant q.
+            for (a, b) in [u, v] {
+                assert_eq!((a as i128 + b as i128 * s as i128).rem_euclid(q as i128), 0);
+            }
+            assert_eq!(
+                (u.0 as i128 * v.1 as i128 - u.1 as i128 * v.0 as i128).abs(),
+                q as i128
+            );
+
+            // The basis is reduced.
+            let dot = u.0 as i128 * v.0 as i128 + (skew as i128).pow(2) * u.1 as i128 * v.1 as i128;
+            assert!(norm(u) <= norm(v));
+            assert!(2 * dot.abs() <= norm(u));
+        }
+    }
+
+    #[test]
+    fn bucket_sieve() {
+        // Primes below and above the row length, rows that are multiples of some primes, and
+        // segments that end within rows. The result must not depend on the segment size.
+        let base: Vec<(u64, u64)> = [(3, 1), (7, 0), (11, 4), (17, 16), (37, 2), (101, 5)]
+            .into_iter()
+            .chain([(1009, 1000), (4099, 17)])
+            .chain(Primes::new(5000).take(50).map(|p| (p, p * p % 997)))
+            .collect();
+        for (row_len, rows) in [(1000, 12), (16, 40)] {
+            let i0 = -(row_len as i64 / 2);
+            let mut expected = vec![0i8; row_len * rows];
+            for (position, x) in expected.iter_mut().enumerate() {
+                let i = i0 + (position % row_len) as i64;
+                let j = (position / row_len + 1) as u64;
+                for &(p, r) in &base {
+                    if (i + (j * r) as i64).rem_euclid(p as i64) == 0 && !j.is_multiple_of(p) {
+                        *x += ilog2_rounded(p) as i8;
+                    }
+                }
+            }
+
+            let small = base.partition_point(|&(p, _)| p < row_len as u64);
+            for segment_size in [64, 1000, 4096, row_len * rows] {
+                let segments = (row_len * rows).div_ceil(segment_size);
+                let mut buckets = vec![Bucket::new(); segments];
+                fill_buckets(&base[small..], row_len, rows, segment_size, &mut buckets);
+                let mut region = vec![0i8; row_len * rows];
+                for (k, segment) in region.chunks_mut(segment_size).enumerate() {
+                    let start = k * segment_size;
+                    sieve_segment(segment, start, row_len, &base[..small], &buckets[k], |_| 0);
+                }
+                assert_eq!(
+                    region, expected,
+                    "{} x {} in {}",
+                    rows, row_len, segment_size
+                );
+            }
+        }
+
+        // With the parameters of the tables, the region is sieved in several segments, and the
+        // largest primes of the factor bases exceed the row length, so they are bucket sieved.
+        for (_, params) in Params::PARAM_TABLE.iter().chain(&Params::SNFS_PARAM_TABLE) {
+            let region = params.sieve_array_size * params.lattice_rows;
+            assert!(region.div_ceil(LatticeSiever::segment_size(params)) > 1);
+            let largest = Primes::new(2).nth(params.rational_base_size - 1).unwrap();
+            assert!(largest > params.sieve_array_size as u64);
+        }
+    }
+
+    #[test]
+    fn rational_threshold_of_wide_y0() {
+        // With Y0 = 2^200 + 1, the norm has more than 200 bits, which doesn't fit into an i8.
+        let mut g = MpPolynomial::new();
+        g[0] = (Integer::from(1) << 200) + 1;
+        g[1] = Integer::from(12345);
+        assert_eq!(rational_threshold(1 << 10, 1 << 10, &g, 20), i8::MIN);
+
+        g[0] = Integer::from(1) << 40;
+        assert_eq!(rational_threshold(1 << 10, 1 << 10, &g, 20), 20 - 51);
+    }
+}
@@ -0,0 +1,31 @@
+mod certificate;
+mod cofact;
+mod cycle;
+mod driver;
+mod ecm;
+mod factorizer;
+mod gfpolynomial;
+mod ideal;
+mod job;
+mod lanczos;
+mod lattice;
+mod linalg;
+mod nfs;
+mod nt;
+pub mod params;
+mod pm1;
+pub mod polynomial;
+pub mod polyselect;
+mod primes;
+mod relfile;
+mod rho;
+mod siqs;
+mod snfs;
+mod sqrt;
+
+pub use certificate::Certificate;
+pub use driver::PrimeFactorization;
+pub use factorizer::{Error, Factorization, Factorizer};
+pub use nfs::Statistics;
+pub use params::Params;
+pub use snfs::SpecialForm;
@@ -65,7 +65,7 @@ impl CscMatrix {
             }
             end.push(ones.len());
             for i in 0..weight {
-                used[ones[ones.len() - i - 1] as usize] = false;
+                used[ones[ones.len() - i - 1]] = false;
             }
         }
 
@@ -86,7 +86,7 @@ impl CscMatrix {
 
     // Returns a view on the transposed matrix. The view is tightly bound to the original CscMatrix
     // and is intended to be used only in composition with the '*'-Operator.
-    pub fn transpose(&self) -> CscMatrixTranspose {
+    pub fn transpose(&self) -> CscMatrixTranspose<'_> {
         CscMatrixTranspose { borrowed: self }
     }
 }
@@ -116,7 +116,7 @@ impl CscMatrixBuilder {
     }
 
     pub fn build(self) -> CscMatrix {
-        assert_eq!(self.end.len() != 0, self.num_rows != 0);
+        assert_eq!(!self.end.is_empty(), self.num_rows != 0);
         CscMatrix::new(self.num_rows, self.end, self.ones)
     }
 }
@@ -130,19 +130,19 @@ impl BlockMatrix {
 
     // Provides a lightweight view on the transposed matrix, which isn't intendend to be used
     // standalone, but as an argument to the '*'-Operator (on any side).
-    pub fn transpose(&self) -> BlockMatrixTranspose {
+    pub fn transpose(&self) -> BlockMatrixTranspose<'_> {
         BlockMatrixTranspose { borrowed: self }
     }
 
     // Calculates the transpose explicity as a two-dimensional vector, in row-major format.
     // TODO: Optimize this to array of vectors?
     pub fn explicit_transpose(&self) -> Vec<Vec<u64>> {
-        let n_words = (self.as_ref().len() + N - 1) / N;
+        let n_words = self.as_ref().len().div_ceil(N);
         let mut res: Vec<Vec<u64>> = vec![vec![0; n_words]; N];
 
         for i in 0..self.as_ref().len() {
-            for j in 0..N {
-                res[j][i / N] |= ((self[i] >> j) & 1) << (i & (N - 1));
+            for (j, row) in res.iter_mut().enumerate() {
+                row[i / N] |= ((self[i] >> j) & 1) << (i & (N - 1));
             }
         }
 
@@ -211,8 +211,8 @@ impl Mul<&BlockMatrix> for &CscMatrix {
 
         let mut j: usize = 0;
         for i in 0..n {
-            while j < self.end[i] as usize {
-                res[self.ones[j] as usize] ^= b[i];
+            while j < self.end[i] {
+                res[self.ones[j]] ^= b[i];
                 j += 1;
             }
         }
@@ -231,8 +231,8 @@ impl<'a> Mul<&BlockMatrix> for &CscMatrixTranspose<'a> {
 
         let mut j: usize = 0;
         for i in 0..n {
-            while j < self.borrowed.end[i] as usize {
-                res[i] ^= b[self.borrowed.ones[j] as usize];
+            while j < self.borrowed.end[i] {
+                res[i] ^= b[self.borrowed.ones[j]];
                 j += 1;
             }
         }
@@ -1,34 +1,281 @@
-mod gfpolynomial;
-mod lanczos;
-mod linalg;
-mod nfs;
-mod nt;
-mod params;
-mod polynomial;
-mod sqrt;
-
-use std::env;
-use std::io;
-use std::io::Write;
-
-use rug::Complete;
-use rug::Integer;
-
-fn main() {
-    env::set_var("RUST_LOG", "debug");
-    env_logger::init();
-
-    print!("Enter number to be factored: ");
-    let _ = io::stdout().flush();
-    let mut buf = String::new();
-    io::stdin()
-        .read_line(&mut buf)
-        .expect("Failed to read integer.");
-    let n = Integer::parse(buf).unwrap().complete();
-    let factors = nfs::factorize(&n);
-
-    println!("Found the following factorizations:\n");
-    for a in factors {
-        println!("{} * {}", &a, (&n / &a).complete());
+mod cli;
+
+use std::{env, fs, io, io::Read, io::Write, process::ExitCode};
+
+use log::LevelFilter;
+use rug::{Complete, Integer};
+
+use cli::{Command, Format, Options};
+use nfs::{polynomial::PolynomialPair, Factorizer, Params, PrimeFactorization, Statistics};
+
+const EXIT_FAILURE: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+
+fn main() -> ExitCode {
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(Command::Help) => {
+            print!("{}", cli::usage());
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Version) => {
+            println!("nfs {}", env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Run(options)) => options,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::usage());
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    init_logger(options.verbosity);
+
+    match run(&options) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(EXIT_FAILURE),
+        Err(RunError::Usage(e)) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(EXIT_USAGE)
+        }
+        Err(RunError::Io(e)) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+// Errors that stop run before all numbers are processed: invalid contents of the files given on the
+// command line, or a failure to read or write them.
+enum RunError {
+    Usage(String),
+    Io(String),
+}
+
+impl From<String> for RunError {
+    fn from(e: String) -> RunError {
+        RunError::Usage(e)
+    }
+}
+
+fn init_logger(verbosity: u32) {
+    let level = match verbosity {
+        0 => LevelFilter::Error,
+        1 => LevelFilter::Warn,
+        2 => LevelFilter::Info,
+        3 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    // RUST_LOG still takes precedence, if set.
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .init();
+}
+
+fn read_to_string(path: &str) -> Result<String, RunError> {
+    if path == "-" {
+        let mut s = String::new();
+        io::stdin()
+            .read_to_string(&mut s)
+            .map_err(|e| RunError::Io(format!("failed to read stdin: {}", e)))?;
+        return Ok(s);
+    }
+    fs::read_to_string(path).map_err(|e| RunError::Io(format!("failed to read {}: {}", path, e)))
+}
+
+// Factors all numbers given in options. Returns whether a factorization was found for each of
+// them, or an error if the input couldn't be read at all.
+fn run(options: &Options) -> Result<bool, RunError> {
+    let mut numbers = options.numbers.clone();
+    if let Some(path) = &options.input {
+        numbers.extend(cli::numbers_from_str(&read_to_string(path)?));
+    }
+    let polynomial = match &options.poly {
+        Some(path) => Some(
+            read_to_string(path)?
+                .parse::<PolynomialPair>()
+                .map_err(|e| format!("{}: {}", path, e))?,
+        ),
+        None => None,
+    };
+    if numbers.is_empty() && options.input.is_none() {
+        numbers = match (&polynomial, &options.snfs) {
+            (Some(pair), _) => vec![pair.n.to_string()],
+            (None, Some(form)) => vec![form.value().to_string()],
+            (None, None) => cli::numbers_from_str(&read_to_string("-")?),
+        };
+    }
+
+    let params_file = match &options.params_file {
+        Some(path) => Some(read_to_string(path)?),
+        None => None,
+    };
+
+    // The certificates of all numbers are appended to the file.
+    if let Some(path) = &options.certify {
+        fs::write(path, "")
+            .map_err(|e| RunError::Io(format!("failed to write {}: {}", path, e)))?;
+    }
+
+    let mut success = true;
+    for s in numbers {
+        let n = match Integer::parse(&s) {
+            Ok(n) => n.complete(),
+            Err(_) => {
+                report_error(options.format, &s, &format!("invalid integer {}", s));
+                success = false;
+                continue;
+            }
+        };
+
+        // Without overrides, the parameters are chosen for each composite part separately.
+        let mut factorizer = Factorizer::new();
+        if let Some(bits) = options.nfs_crossover {
+            factorizer.set_nfs_crossover(bits);
+        }
+        if let Some(dir) = &options.job_dir {
+            factorizer.set_job_dir(dir);
+        }
+        if let Some(path) = &options.import_relations {
+            factorizer.set_relations_import(path);
+        }
+        if let Some(path) = &options.export_relations {
+            factorizer.set_relations_export(path);
+        }
+        if let Some(pair) = &polynomial {
+            factorizer.set_polynomial(pair.clone());
+        }
+        if let Some(path) = &options.export_poly {
+            factorizer.set_polynomial_export(path);
+        }
+        if let Some(form) = &options.snfs {
+            factorizer.set_special_form(form.clone());
+        }
+        if params_file.is_some() || !options.overrides.is_empty() {
+            // The overrides apply to the SNFS parameters if the numbers divide the special form.
+            let mut params = match &options.snfs {
+                Some(form) if form.value().is_divisible(&n) => Params::new_snfs(form.difficulty()),
+                _ => Params::new(&n),
+            };
+            if let Some(contents) = &params_file {
+                params.apply_assignments(contents)?;
+            }
+            for (name, value) in &options.overrides {
+                params.set(name, value)?;
+            }
+            factorizer.set_params(params);
+        }
+
+        let result = factorizer.factor(&n).map_err(|e| e.to_string());
+        let result = match &options.certify {
+            Some(path) => result.and_then(|mut factorization| {
+                certify(&factorizer, &mut factorization, path)?;
+                Ok(factorization)
+            }),
+            None => result,
+        };
+        match result {
+            Ok(factorization) => report(options.format, &factorization),
+            Err(e) => {
+                report_error(options.format, &s, &e);
+                success = false;
+            }
+        }
+    }
+
+    Ok(success)
+}
+
+// Proves the prime factors and appends their certificate to the file at path. All factors are
+// proven afterwards, so none of them is reported as a probable prime.
+fn certify(
+    factorizer: &Factorizer,
+    factorization: &mut PrimeFactorization,
+    path: &str,
+) -> Result<(), String> {
+    let primes: Vec<Integer> = factorization
+        .factors
+        .iter()
+        .map(|(p, _)| p.clone())
+        .collect();
+    let certificate = factorizer.certify(&primes).map_err(|e| e.to_string())?;
+    certificate.verify()?;
+    fs::OpenOptions::new()
+        .append(true)
+        .open(path)
+        .and_then(|mut file| write!(file, "{}", certificate))
+        .map_err(|e| format!("failed to write {}: {}", path, e))?;
+    factorization.probable_primes.clear();
+    Ok(())
+}
+
+fn report(format: Format, factorization: &PrimeFactorization) {
+    match format {
+        Format::Plain => {
+            let factors: Vec<String> = factorization
+                .factors
+                .iter()
+                .map(|(p, e)| match e {
+                    1 => p.to_string(),
+                    _ => format!("{}^{}", p, e),
+                })
+                .collect();
+            println!("{} = {}", factorization.n, factors.join(" * "));
+        }
+        Format::Json => {
+            let factors: Vec<String> = factorization
+                .factors
+                .iter()
+                .map(|(p, e)| {
+                    format!(
+                        "{{\"prime\":\"{}\",\"exponent\":{},\"proven\":{}}}",
+                        p,
+                        e,
+                        !factorization.probable_primes.contains(p)
+                    )
+                })
+                .collect();
+            let statistics: Vec<String> = factorization
+                .statistics
+                .iter()
+                .map(statistics_json)
+                .collect();
+            println!(
+                "{{\"n\":\"{}\",\"factors\":[{}],\"statistics\":[{}]}}",
+                factorization.n,
+                factors.join(","),
+                statistics.join(",")
+            );
+        }
+    }
+}
+
+fn statistics_json(statistics: &Statistics) -> String {
+    format!(
+        "{{\"rational_base_size\":{},\"algebraic_base_size\":{},\"quad_char_base_size\":{},\
+         \"relations\":{},\"partial_relations\":{},\"cycles\":{},\"dependencies\":{},\
+         \"special_q\":{},\"sieve_time\":{},\"linalg_time\":{},\"sqrt_time\":{}}}",
+        statistics.rational_base_size,
+        statistics.algebraic_base_size,
+        statistics.quad_char_base_size,
+        statistics.relations,
+        statistics.partial_relations,
+        statistics.cycles,
+        statistics.dependencies,
+        statistics.special_q,
+        statistics.sieve_time.as_secs_f64(),
+        statistics.linalg_time.as_secs_f64(),
+        statistics.sqrt_time.as_secs_f64()
+    )
+}
+
+fn report_error(format: Format, n: &str, error: &str) {
+    match format {
+        Format::Plain => eprintln!("{}: {}", n, error),
+        Format::Json => println!(
+            "{{\"n\":\"{}\",\"error\":\"{}\"}}",
+            cli::json_escape(n),
+            cli::json_escape(error)
+        ),
     }
 }
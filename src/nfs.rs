@@ -1,91 +1,259 @@
-use std::{cmp::min, mem::swap};
+use std::{
+    cmp::min,
+    collections::{BTreeMap, HashSet},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    iter,
+    mem::swap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
-use log::{debug, info};
+use log::{debug, info, warn};
 use rug::{
     integer::IntegerExt64,
-    ops::{NegAssign, Pow},
+    ops::{NegAssign, Pow, RemRounding},
     Complete, Integer,
 };
 
 use crate::{
+    cofact,
+    cycle::CycleFinder,
+    ideal::{self, BadIdeal},
+    job::{self, Job},
     lanczos,
+    lattice::LatticeSiever,
     linalg::CscMatrixBuilder,
     nt,
     params::{Params, OVERSQUARENESS},
-    polynomial::{self, MpPolynomial, Polynomial},
+    polynomial::{MpPolynomial, Polynomial, PolynomialPair},
+    polyselect,
+    primes::Primes,
+    relfile::RelationLine,
     sqrt,
 };
 
-fn rational_factor_base(m: &Integer, params: &Params) -> Vec<(u64, u64)> {
-    let mut base: Vec<(u64, u64)> = Vec::new();
+// Number of special-q per thread in each round of sieving.
+const SPECIAL_Q_PER_THREAD: usize = 4;
 
-    let mut p: u64 = 2;
-    while base.len() < params.rational_base_size {
-        if nt::miller_rabin(p) {
-            base.push((p, m.mod_u64(p)));
-        }
-        p += 1;
+// Whether the prime ideal (p, r) divides a + b * alpha, or the prime p divides the rational norm if
+// r is the root of g mod p. The root r = p stands for the projective root, which belongs to the
+// primes dividing the leading coefficient and divides exactly the pairs with p | b.
+fn divides((p, r): (u64, u64), a: i64, b: u64) -> bool {
+    if r == p {
+        b.is_multiple_of(p)
+    } else {
+        nt::mod_add(a.rem_euclid(p as i64) as u64, nt::mod_mul(b % p, r, p), p) == 0
     }
+}
 
-    base
+fn rational_factor_base(g: &MpPolynomial, params: &Params) -> Vec<(u64, u64)> {
+    Primes::new(2)
+        .take(params.rational_base_size)
+        .map(|p| {
+            let y1 = g[1].mod_u64(p);
+            let r = match y1 {
+                0 => p,
+                _ => nt::mod_mul(nt::mod_sub(0, g[0].mod_u64(p), p), nt::mod_inv(y1, p), p),
+            };
+            (p, r)
+        })
+        .collect()
 }
 
 fn algebraic_factor_base(f: &MpPolynomial, params: &Params) -> Vec<(u64, u64)> {
     let mut base: Vec<(u64, u64)> = Vec::new();
-    let mut p: u64 = 2;
 
-    while base.len() < params.algebraic_base_size {
-        if nt::miller_rabin(p) {
-            let roots = f.find_roots_mod_p(p);
-            base.extend(roots.iter().map(|r| (p, *r)));
+    for p in Primes::new(2) {
+        if base.len() >= params.algebraic_base_size {
+            break;
+        }
+        let roots = f.find_roots_mod_p(p);
+        base.extend(roots.iter().map(|r| (p, *r)));
+        if f[f.degree()].is_divisible_u64(p) {
+            base.push((p, p));
         }
-        p += 1;
     }
 
     base.truncate(params.algebraic_base_size);
     base
 }
 
-fn quad_char_base(mut p: u64, f: &MpPolynomial, params: &Params) -> Vec<(u64, u64)> {
+fn quad_char_base(start: u64, f: &MpPolynomial, params: &Params) -> Vec<(u64, u64)> {
     let mut base: Vec<(u64, u64)> = Vec::new();
     let f_derivative = f.derivative();
 
-    while base.len() < params.quad_char_base_size {
-        if nt::miller_rabin(p) {
-            let roots = f.find_roots_mod_p(p);
-            for s in roots {
-                if !f_derivative.evaluate(s).is_divisible_u64(p) {
-                    base.push((p, s));
-                }
+    for p in Primes::new(start) {
+        if base.len() >= params.quad_char_base_size {
+            break;
+        }
+        if f[f.degree()].is_divisible_u64(p) {
+            continue;
+        }
+        let roots = f.find_roots_mod_p(p);
+        for s in roots {
+            if !f_derivative.evaluate(s).is_divisible_u64(p) {
+                base.push((p, s));
             }
         }
-        p += 1;
     }
 
     base.truncate(params.quad_char_base_size);
     base
 }
 
-fn ilog2_rounded(x: u64) -> u32 {
-    ((x * x).ilog2() + 1) >> 1
+// The factor bases on both sides and the quadratic characters. After one row for the sign on the
+// rational side, the rows of the matrix correspond to their elements in this order. Unless f is
+// monic and g = x - m, a last row makes the number of relations in each dependency even, which the
+// square root needs. The row of a bad ideal in the algebraic factor base takes the valuation at
+// the first ideal above it, and the others get additional rows at the end.
+struct FactorBase {
+    rational: Vec<(u64, u64)>,
+    algebraic: Vec<(u64, u64)>,
+    quad_char: Vec<(u64, u64)>,
+    parity_row: bool,
+    // The bad ideals with their index in the algebraic factor base and their first additional row.
+    bad: Vec<(usize, usize, BadIdeal)>,
 }
 
-fn line_sieve(b: u64, sieve_array: &mut Vec<i8>, base: &Vec<(u64, u64)>) {
-    let a0 = -(sieve_array.len() as i64 / 2);
+impl FactorBase {
+    fn new(g: &MpPolynomial, f: &MpPolynomial, params: &Params) -> FactorBase {
+        let rational = rational_factor_base(g, params);
+        let algebraic = algebraic_factor_base(f, params);
+
+        // The primes used for quadratic characters must not divide any norm, so they are chosen
+        // above all primes that may occur in a relation.
+        let mut largest_prime = algebraic.last().unwrap().0;
+        if params.large_primes > 0 {
+            largest_prime = largest_prime.max(params.algebraic_large_prime_bound);
+        }
+        let quad_char = quad_char_base(largest_prime + 1, f, params);
 
-    for (p, r) in base {
-        if b % p != 0 {
-            let log2p = ilog2_rounded(*p) as i8;
-            let mut i = (((-(((b * r) % p) as i64)) + *p as i64 - a0) % *p as i64) as usize;
-            while i < sieve_array.len() {
-                sieve_array[i] += log2p;
-                i += *p as usize;
+        let parity_row = f[f.degree()] != 1 || g[1] != 1;
+        let mut row = 1 + rational.len() + algebraic.len() + quad_char.len() + parity_row as usize;
+        let mut bad: Vec<(usize, usize, BadIdeal)> = Vec::new();
+        for (i, &(p, r)) in algebraic.iter().enumerate() {
+            if let Some(ideal) = BadIdeal::new(f, p, r) {
+                let extra_rows = ideal.len().saturating_sub(1);
+                bad.push((i, row, ideal));
+                row += extra_rows;
             }
         }
+
+        FactorBase {
+            rational,
+            algebraic,
+            quad_char,
+            parity_row,
+            bad,
+        }
     }
+
+    fn len(&self) -> usize {
+        let extra_rows: usize = self
+            .bad
+            .iter()
+            .map(|(_, _, ideal)| ideal.len().saturating_sub(1))
+            .sum();
+        1 + self.rational.len()
+            + self.algebraic.len()
+            + self.quad_char.len()
+            + self.parity_row as usize
+            + extra_rows
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum LargePrime {
+    Rational(u64),
+    // The prime ideal (p, r) of degree one, r = p for the projective root.
+    Algebraic(u64, u64),
+}
+
+// A coprime pair (a, b) such that the rational norm and a + b * alpha are smooth over the factor
+// base, except for the large primes.
+struct Relation {
+    a: i64,
+    b: u64,
+    ones_pos: Vec<usize>,
+    large_primes: Vec<LargePrime>,
 }
 
-fn norm(f: &MpPolynomial, a: i64, b: u64) -> Integer {
+// The relations found so far, grouped into the columns of the matrix. Each column is a set of
+// relations, either a single full relation or a cycle of relations with large primes.
+struct RelationSet {
+    relations: Vec<Relation>,
+    columns: Vec<Vec<usize>>,
+    cycle_finder: CycleFinder<LargePrime>,
+    seen: HashSet<(i64, u64)>,
+}
+
+impl RelationSet {
+    fn new() -> RelationSet {
+        RelationSet {
+            relations: Vec::new(),
+            columns: Vec::new(),
+            cycle_finder: CycleFinder::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    // Adds the relation, unless it was found before. Returns whether it was added.
+    fn add(&mut self, relation: Relation) -> bool {
+        if !self.seen.insert((relation.a, relation.b)) {
+            return false;
+        }
+        let i = self.relations.len();
+        match relation.large_primes[..] {
+            [] => self.columns.push(vec![i]),
+            [p] => self.columns.extend(self.cycle_finder.add(None, Some(p), i)),
+            [p, q] => self
+                .columns
+                .extend(self.cycle_finder.add(Some(p), Some(q), i)),
+            _ => unreachable!(),
+        }
+        self.relations.push(relation);
+        true
+    }
+}
+
+// Files for exchanging data with other implementations of the number field sieve.
+#[derive(Clone, Debug, Default)]
+pub struct ExternalFiles {
+    // Relations in the format of msieve and CADO-NFS that are used in addition to those found by
+    // sieving. They must belong to the same polynomial.
+    pub import_relations: Option<PathBuf>,
+    // All relations are written to this file in the same format after sieving.
+    pub export_relations: Option<PathBuf>,
+    // The polynomial pair is written to this file in the .poly format of msieve and CADO-NFS.
+    pub export_polynomial: Option<PathBuf>,
+}
+
+// Summary of a run of the number field sieve.
+#[derive(Clone, Debug, Default)]
+pub struct Statistics {
+    pub rational_base_size: usize,
+    pub algebraic_base_size: usize,
+    pub quad_char_base_size: usize,
+    pub relations: usize,
+    pub partial_relations: usize,
+    pub cycles: usize,
+    pub dependencies: usize,
+    pub special_q: usize,
+    pub sieve_time: Duration,
+    pub linalg_time: Duration,
+    pub sqrt_time: Duration,
+}
+
+// The homogenized value (-b)^d * f(-a / b), which is the norm of a + b * alpha times the leading
+// coefficient of f. For the rational polynomial g = Y1 * x + Y0, this is the rational norm
+// Y1 * a - Y0 * b, which is a + b * m for g = x - m.
+pub fn norm(f: &MpPolynomial, a: i64, b: u64) -> Integer {
     let d = f.degree();
     let mut u = Integer::from(1);
     let mut v = Integer::from(-(b as i64)).pow(d as u32);
@@ -100,111 +268,508 @@ fn norm(f: &MpPolynomial, a: i64, b: u64) -> Integer {
     result
 }
 
-pub fn factorize(n: &Integer) -> Vec<Integer> {
-    let params = Params::new(&n);
-    let (f, m) = polynomial::select(n, &params);
-
-    info!("set d = {}, m = {}", params.polynomial_degree, &m);
-    info!("selected the polynomial {}", &f);
+// Divides the norm by the primes of the factor base that divide it, and calls found with the index
+// of the ideal in base and the exponent. For a coprime pair, at most one of the ideals above a
+// prime divides a + b * alpha, so the whole valuation at the prime belongs to the first one found.
+fn trial_divide(
+    norm: &mut Integer,
+    a: i64,
+    b: u64,
+    base: &[(u64, u64)],
+    mut found: impl FnMut(usize, u32),
+) {
+    let mut i = 0;
+    while i < base.len() {
+        let p = base[i].0;
+        let end = i + base[i..].partition_point(|&(q, _)| q == p);
+        if let Some(j) = (i..end).find(|&j| divides(base[j], a, b)) {
+            found(j, norm.remove_factor_mut(&Integer::from(p)));
+        }
+        i = end;
+    }
+}
 
-    // Maybe check that the polynomial is irreducible
-    let rational_base = rational_factor_base(&m, &params);
-    let algebraic_base = algebraic_factor_base(&f, &params);
-    let quad_char_base = quad_char_base(algebraic_base.last().unwrap().0 + 1, &f, &params);
+// The valuations of a + b * alpha at the ideals of the algebraic factor base, as pairs of a row of
+// the matrix and the exponent, after dividing them out of alg_norm. At a bad ideal, the valuation
+// is split among the ideals above it, and None is returned if that fails.
+fn algebraic_valuations(
+    alg_norm: &mut Integer,
+    a: i64,
+    b: u64,
+    base: &FactorBase,
+) -> Option<Vec<(usize, u32)>> {
+    let algebraic_begin = 1 + base.rational.len();
+    let mut valuations: Vec<(usize, u32)> = Vec::new();
+    let mut split = true;
+    trial_divide(alg_norm, a, b, &base.algebraic, |i, e| {
+        let Ok(j) = base.bad.binary_search_by_key(&i, |(i, _, _)| *i) else {
+            valuations.push((algebraic_begin + i, e));
+            return;
+        };
+        let (_, extra_begin, ideal) = &base.bad[j];
+        let Some(split_valuations) = ideal.valuations(a, b, e) else {
+            split = false;
+            return;
+        };
+        for (k, v) in split_valuations.into_iter().enumerate() {
+            match k {
+                0 => valuations.push((algebraic_begin + i, v)),
+                _ => valuations.push((extra_begin + k - 1, v)),
+            }
+        }
+    });
+    split.then_some(valuations)
+}
 
+// Trial divides the rational norm and the norm of a + b * alpha over the factor base. Returns the
+// relation if both are smooth, up to at most params.large_primes large primes below the large prime
+// bounds.
+fn find_relation(
+    a: i64,
+    b: u64,
+    g: &MpPolynomial,
+    f: &MpPolynomial,
+    base: &FactorBase,
+    params: &Params,
+) -> Option<Relation> {
     let rational_begin: usize = 1;
-    let algebraic_begin = rational_begin + rational_base.len();
-    let quad_char_begin = algebraic_begin + algebraic_base.len();
-    let base_len = quad_char_begin + quad_char_base.len();
+    let algebraic_begin = rational_begin + base.rational.len();
+    let quad_char_begin = algebraic_begin + base.algebraic.len();
+    let mut ones_pos: Vec<usize> = Vec::new();
+
+    // Trial divide on the rational side.
+    let mut num = norm(g, a, b);
+    if num < 0 {
+        ones_pos.push(0);
+        num.neg_assign();
+    }
+    trial_divide(&mut num, a, b, &base.rational, |i, e| {
+        if e & 1 == 1 {
+            ones_pos.push(rational_begin + i);
+        }
+    });
+    let rational_large_primes =
+        cofact::large_primes(&num, params.rational_large_prime_bound, params.large_primes)?;
+
+    // Trial divide on the algebraic side.
+    let mut alg_norm = norm(f, a, b);
+    for (row, e) in algebraic_valuations(&mut alg_norm, a, b, base)? {
+        if e & 1 == 1 {
+            ones_pos.push(row);
+        }
+    }
+    alg_norm.abs_mut();
+    let algebraic_large_primes = cofact::large_primes(
+        &alg_norm,
+        params.algebraic_large_prime_bound,
+        params.large_primes - rational_large_primes.len(),
+    )?;
+
+    for (i, (p, s)) in base.quad_char.iter().enumerate() {
+        let x = nt::mod_add(
+            a.rem_euclid(*p as i64) as u64,
+            nt::mod_mul(b % p, *s, *p),
+            *p,
+        );
+        if nt::jacobi(x, *p) == -1 {
+            ones_pos.push(quad_char_begin + i);
+        }
+    }
+    if base.parity_row {
+        ones_pos.push(quad_char_begin + base.quad_char.len());
+    }
+
+    // A prime p dividing the norm of a + b * alpha divides f(-a / b) * b^d, so the prime ideal
+    // above it is (p, -a / b mod p), or the projective one if p divides b.
+    let mut large_primes: Vec<LargePrime> = rational_large_primes
+        .into_iter()
+        .map(LargePrime::Rational)
+        .collect();
+    large_primes.extend(algebraic_large_primes.into_iter().map(|p| {
+        if b.is_multiple_of(p) {
+            return LargePrime::Algebraic(p, p);
+        }
+        let a = a.rem_euclid(p as i64) as u64;
+        LargePrime::Algebraic(
+            p,
+            nt::mod_mul(nt::mod_sub(0, a, p), nt::mod_inv(b % p, p), p),
+        )
+    }));
+    // The large prime ideals stand for a single ideal of the number field, which a bad one doesn't.
+    if large_primes.iter().any(|large_prime| match *large_prime {
+        LargePrime::Algebraic(p, r) => ideal::is_bad(f, p, r),
+        LargePrime::Rational(_) => false,
+    }) {
+        return None;
+    }
+
+    Some(Relation {
+        a,
+        b,
+        ones_pos,
+        large_primes,
+    })
+}
+
+// Recovers the complete factorizations of the norms of a relation.
+fn relation_line(
+    relation: &Relation,
+    g: &MpPolynomial,
+    f: &MpPolynomial,
+    base: &FactorBase,
+) -> RelationLine {
+    let (a, b) = (relation.a, relation.b);
+    let mut rational: Vec<u64> = Vec::new();
+    trial_divide(&mut norm(g, a, b), a, b, &base.rational, |i, e| {
+        rational.extend(iter::repeat_n(base.rational[i].0, e as usize));
+    });
+    let mut algebraic: Vec<u64> = Vec::new();
+    trial_divide(&mut norm(f, a, b), a, b, &base.algebraic, |i, e| {
+        algebraic.extend(iter::repeat_n(base.algebraic[i].0, e as usize));
+    });
+    for large_prime in &relation.large_primes {
+        match *large_prime {
+            LargePrime::Rational(p) => rational.push(p),
+            LargePrime::Algebraic(p, _) => algebraic.push(p),
+        }
+    }
+    rational.sort_unstable();
+    algebraic.sort_unstable();
+
+    RelationLine {
+        a,
+        b,
+        rational,
+        algebraic,
+    }
+}
+
+// Adds the relations from a file in the format of msieve and CADO-NFS. Relations that don't belong
+// to the polynomial are skipped, as well as those that aren't smooth over the factor base.
+fn import_relations(
+    path: &Path,
+    g: &MpPolynomial,
+    f: &MpPolynomial,
+    base: &FactorBase,
+    params: &Params,
+    relations: &mut RelationSet,
+) -> io::Result<()> {
+    let s = fs::read_to_string(path).map_err(job::with_path(path))?;
+    let (mut added, mut invalid, mut not_smooth) = (0, 0, 0);
+
+    for (i, line) in s.lines().map(str::trim).enumerate() {
+        // msieve starts its relation files with a line containing n.
+        if line.is_empty() || line.starts_with('#') || line.starts_with('N') {
+            continue;
+        }
+        let line = match line
+            .parse::<RelationLine>()
+            .and_then(|line| line.check(g, f).map(|()| line))
+        {
+            Ok(line) => line,
+            Err(e) => {
+                debug!("{}:{}: {}", path.display(), i + 1, e);
+                invalid += 1;
+                continue;
+            }
+        };
+        match find_relation(line.a, line.b, g, f, base, params) {
+            Some(relation) => added += relations.add(relation) as usize,
+            None => not_smooth += 1,
+        }
+    }
+
+    if invalid > 0 {
+        warn!(
+            "skipped {} invalid relations in {}, they may belong to a different polynomial",
+            invalid,
+            path.display()
+        );
+    }
+    info!(
+        "imported {} new relations from {}, {} are not smooth over the factor base",
+        added,
+        path.display(),
+        not_smooth
+    );
+    Ok(())
+}
+
+fn export_relations(
+    path: &Path,
+    relations: &[Relation],
+    n: &Integer,
+    g: &MpPolynomial,
+    f: &MpPolynomial,
+    base: &FactorBase,
+) -> io::Result<()> {
+    let write = || -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "# n = {}, f = {}, g = {}", n, f, g)?;
+        for relation in relations {
+            writeln!(writer, "{}", relation_line(relation, g, f, base))?;
+        }
+        writer.flush()
+    };
+    write().map_err(job::with_path(path))?;
+    info!("wrote {} relations to {}", relations.len(), path.display());
+    Ok(())
+}
+
+// Runs the number field sieve on n, with the given polynomial pair if there is one. If a job is
+// given, the polynomial and the relations are stored in it, and relations from a previous run are
+// used.
+pub fn factorize(
+    n: &Integer,
+    params: &Params,
+    mut job: Option<&mut Job>,
+    files: &ExternalFiles,
+    polynomial: Option<&PolynomialPair>,
+) -> io::Result<(Vec<Integer>, Statistics)> {
+    let mut statistics = Statistics::default();
+    let stored_polynomial = match &job {
+        Some(job) => job.load_polynomial()?,
+        None => None,
+    };
+    let pair = match (stored_polynomial, polynomial) {
+        (Some(stored), given) => {
+            if given.is_some_and(|given| (&given.f, &given.y0) != (&stored.f, &stored.y0)) {
+                warn!("the job was started with a different polynomial, which is used instead");
+            }
+            info!("using the polynomial of the job");
+            stored
+        }
+        (None, given) => {
+            let pair = match given {
+                Some(given) => {
+                    info!("using the given polynomial");
+                    given.clone()
+                }
+                None => polyselect::select(n, params),
+            };
+            if let Some(job) = &job {
+                job.save_polynomial(&pair)?;
+            }
+            pair
+        }
+    };
+    if let Some(path) = &files.export_polynomial {
+        fs::write(path, pair.to_string()).map_err(job::with_path(path))?;
+    }
+
+    // The common root of f and g modulo n. If Y1 is not invertible, it shares a factor with n.
+    let m = match pair.y1.clone().invert(n) {
+        Ok(y1_inverse) => (-(&pair.y0 * y1_inverse)).rem_euc(n),
+        Err(y1) => {
+            let d = y1.gcd(n);
+            if &d == n {
+                return Ok((Vec::new(), statistics));
+            }
+            return Ok((vec![min((n / &d).complete(), d)], statistics));
+        }
+    };
+    let (f, g) = (pair.f.clone(), pair.g());
+    // The lattice sieve works with an integer skewness.
+    let skew = pair.skew.round().clamp(1.0, (1 << 20) as f64) as u64;
+    info!("set d = {}, m = {}", f.degree(), &m);
+    info!("selected the polynomials f = {} and g = {}", &f, &g);
+    info!(
+        "Murphy E of the polynomials: {:.3e}",
+        polyselect::murphy_e(&f, &g, pair.skew, params)
+    );
+
+    // Maybe check that the polynomial is irreducible
+    let start = Instant::now();
+    let base = FactorBase::new(&g, &f, params);
+    let base_len = base.len();
 
     info!(
         "set up factor base consisting of {} primes on the rational side, {} prime ideals on the \
          algebraic side and {} quadratic characters (total size: {})",
-        rational_base.len(),
-        algebraic_base.len(),
-        quad_char_base.len(),
+        base.rational.len(),
+        base.algebraic.len(),
+        base.quad_char.len(),
         base_len
     );
+    debug!(
+        "{} bad ideals in the algebraic factor base: {:?}",
+        base.bad.len(),
+        base.bad
+            .iter()
+            .map(|(_, _, ideal)| (ideal.p, ideal.r, ideal.len()))
+            .collect::<Vec<_>>()
+    );
 
-    let mut matrix_builder = CscMatrixBuilder::new();
-    matrix_builder.set_num_rows(quad_char_begin + quad_char_base.len());
-    let mut relations: Vec<(i64, u64)> = Vec::new();
-
-    let mut rational_sieve_array: Vec<i8> = vec![0; params.sieve_array_size];
-    let mut algebraic_sieve_array: Vec<i8> = vec![0; params.sieve_array_size];
-
-    for b in 1.. {
-        rational_sieve_array
-            .fill(-((ilog2_rounded(b) + m.significant_bits()) as i8) + params.rational_fudge);
-        line_sieve(b, &mut rational_sieve_array, &rational_base);
-
-        algebraic_sieve_array.fill(-params.algebraic_threshold);
-        line_sieve(b, &mut algebraic_sieve_array, &algebraic_base);
-
-        let a0 = -(params.sieve_array_size as i64 / 2);
-        // Consider unsafe access here to avoid bounds checks.
-        for i in 0..params.sieve_array_size {
-            if rational_sieve_array[i] >= 0 && algebraic_sieve_array[i] >= 0 {
-                let a = a0 + i as i64;
-                if nt::gcd(((a % b as i64) + b as i64) as u64, b) != 1 || a == 0 {
-                    continue;
-                }
+    let mut relations = RelationSet::new();
 
-                let mut ones_pos: Vec<usize> = Vec::new();
+    // The special-q are the prime ideals of the algebraic factor base with norm in the configured
+    // range, except the projective ones. If they run out, the smaller ones are used as well, in
+    // decreasing order.
+    let mut special_q: Vec<(u64, u64)> = base
+        .algebraic
+        .iter()
+        .filter(|(q, s)| q != s && (params.special_q_min..=params.special_q_max).contains(q))
+        .copied()
+        .collect();
+    special_q.extend(
+        base.algebraic
+            .iter()
+            .rev()
+            .filter(|(q, s)| q != s && *q < params.special_q_min),
+    );
 
-                // Trial divide on the rational side.
-                let mut num = a + (b * &m).complete();
-                if num < 0 {
-                    ones_pos.push(0);
-                    num.neg_assign();
-                }
-                for (i, (p, _)) in rational_base.iter().enumerate() {
-                    let e = num.remove_factor_mut(&Integer::from(*p));
-                    if e & 1 == 1 {
-                        ones_pos.push(rational_begin + i);
-                    }
-                }
+    if let Some(job) = &mut job {
+        let stored = job.resume()?;
+        for (a, b) in stored.relations {
+            if let Some(relation) = find_relation(a, b, &g, &f, &base, params) {
+                relations.add(relation);
+            }
+        }
+        let completed: HashSet<(u64, u64)> = stored.completed_special_q.into_iter().collect();
+        special_q.retain(|q| !completed.contains(q));
+        statistics.special_q = completed.len();
+        info!(
+            "resuming with {} relations from {} special-q",
+            relations.relations.len(),
+            completed.len()
+        );
+    }
 
-                // Trial divide on the algebraic side.
-                let mut alg_norm = norm(&f, a, b);
-                for (i, (p, r)) in algebraic_base.iter().enumerate() {
-                    if (a + b as i64 * *r as i64) % *p as i64 == 0 {
-                        let e = alg_norm.remove_factor_mut(&Integer::from(*p));
-                        if e & 1 == 1 {
-                            ones_pos.push(algebraic_begin + i);
-                        }
-                    }
-                }
+    if let Some(path) = &files.import_relations {
+        import_relations(path, &g, &f, &base, params, &mut relations)?;
+    }
 
-                if num == 1 && alg_norm == 1 {
-                    // smooth pair (a, b) found!
-                    for (i, (p, s)) in quad_char_base.iter().enumerate() {
-                        if nt::legendre(
-                            (((a + b as i64 * *s as i64) % *p as i64 + *p as i64) % *p as i64)
-                                as u64,
-                            *p,
-                        ) == p - 1
-                        {
-                            ones_pos.push(quad_char_begin + i);
-                        }
+    let threads = match params.threads {
+        0 => thread::available_parallelism().map_or(1, |t| t.get()),
+        t => t,
+    };
+    info!("sieving with {} threads", threads);
+
+    // The special-q are sieved in rounds. In each round, the workers take the next special-q in
+    // turn and put the relations found into a shared collector. Afterwards, they are merged in the
+    // order of the special-q, so that the result doesn't depend on the number of threads.
+    let mut rounds = special_q.chunks(SPECIAL_Q_PER_THREAD * threads);
+    while relations.columns.len() < base_len + OVERSQUARENESS {
+        let Some(round) = rounds.next() else {
+            break;
+        };
+        let next = AtomicUsize::new(0);
+        let collector: Mutex<BTreeMap<usize, Vec<Relation>>> = Mutex::new(BTreeMap::new());
+
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| {
+                    let mut siever = LatticeSiever::new(params, skew);
+                    loop {
+                        let k = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(&(q, s)) = round.get(k) else {
+                            break;
+                        };
+                        let found: Vec<Relation> = siever
+                            .sieve(q, s, &g, &base.rational, &base.algebraic, params)
+                            .into_iter()
+                            .filter_map(|(a, b)| find_relation(a, b, &g, &f, &base, params))
+                            .collect();
+                        collector.lock().unwrap().insert(k, found);
                     }
-                    matrix_builder.add_col(ones_pos);
-                    relations.push((a, b));
+                });
+            }
+        });
+
+        let collected = collector.into_inner().unwrap();
+        for (&q, found) in round.iter().zip(collected.into_values()) {
+            let mut added: Vec<(i64, u64)> = Vec::new();
+            for relation in found {
+                let pair = (relation.a, relation.b);
+                if relations.add(relation) {
+                    added.push(pair);
                 }
             }
-        }
+            if let Some(job) = &mut job {
+                job.append_relations(q, &added)?;
+            }
 
-        if relations.len() >= base_len + OVERSQUARENESS {
-            break;
+            statistics.special_q += 1;
+            if relations.columns.len() >= base_len + OVERSQUARENESS {
+                break;
+            }
+            debug!(
+                "collected {} relations and {} matrix columns after sieving q = {}",
+                relations.relations.len(),
+                relations.columns.len(),
+                q.0
+            );
         }
-        debug!("collected {} relations", relations.len());
     }
 
-    info!("collected {} relations", relations.len());
+    let RelationSet {
+        relations, columns, ..
+    } = relations;
+    if let Some(path) = &files.export_relations {
+        export_relations(path, &relations, n, &g, &f, &base)?;
+    }
+
+    let full_relations = relations
+        .iter()
+        .filter(|relation| relation.large_primes.is_empty())
+        .count();
+    statistics.rational_base_size = base.rational.len();
+    statistics.algebraic_base_size = base.algebraic.len();
+    statistics.quad_char_base_size = base.quad_char.len();
+    statistics.relations = full_relations;
+    statistics.partial_relations = relations.len() - full_relations;
+    statistics.cycles = columns.len() - full_relations;
+    statistics.sieve_time = start.elapsed();
+
+    if columns.len() < base_len + OVERSQUARENESS {
+        warn!(
+            "ran out of special-q with {} of {} relations",
+            columns.len(),
+            base_len + OVERSQUARENESS
+        );
+        return Ok((Vec::new(), statistics));
+    }
 
+    info!(
+        "collected {} full relations and {} cycles from {} partial relations",
+        statistics.relations, statistics.cycles, statistics.partial_relations
+    );
+
+    let start = Instant::now();
+    let mut matrix_builder = CscMatrixBuilder::new();
+    matrix_builder.set_num_rows(base_len);
+    for column in &columns {
+        // The rows of a cycle are the sum of the rows of its relations modulo 2.
+        let mut ones_pos: Vec<usize> = column
+            .iter()
+            .flat_map(|&i| relations[i].ones_pos.iter().copied())
+            .collect();
+        ones_pos.sort_unstable();
+        let mut odd: Vec<usize> = Vec::new();
+        for row in ones_pos {
+            if odd.last() == Some(&row) {
+                odd.pop();
+            } else {
+                odd.push(row);
+            }
+        }
+        matrix_builder.add_col(odd);
+    }
     let (mat, num_dependencies) = lanczos::find_dependencies(&matrix_builder.build());
+    statistics.dependencies = num_dependencies as usize;
+    statistics.linalg_time = start.elapsed();
+
+    let start = Instant::now();
     let mut factors: Vec<Integer> = Vec::new();
 
+    // The square root is taken in Z[omega] for the monic polynomial F(x) = c^(d - 1) * f(x / c)
+    // with the root omega = c * alpha, where c is the leading coefficient of f. Modulo n, omega
+    // maps to c * m.
+    let c = f[f.degree()].clone();
+    let f_monic = f.monic();
+    let f_monic_derivative = f_monic.derivative();
+    let omega = (&c * &m).complete().rem_euc(n);
+
     for i in 0..num_dependencies {
         info!(
             "processing {}-{} dependency",
@@ -220,28 +785,44 @@ pub fn factorize(n: &Integer) -> Vec<Integer> {
             }
         );
 
+        // A relation contained in an even number of the columns can be left out.
+        let mut in_dependency = vec![false; relations.len()];
+        for (j, column) in columns.iter().enumerate() {
+            if (mat[j] >> i) & 1 == 1 {
+                for &k in column {
+                    in_dependency[k] ^= true;
+                }
+            }
+        }
+
         let mut rational: Vec<Integer> = Vec::new();
         let mut algebraic: Vec<MpPolynomial> = Vec::new();
 
-        for (j, (a, b)) in relations.iter().enumerate() {
-            if (mat[j] >> i) & 1 == 1 {
-                rational.push(a + (b * &m).complete());
-                let mut g = MpPolynomial::new();
-                g[0] = Integer::from(*a);
-                g[1] = Integer::from(*b);
-                algebraic.push(g);
-            }
+        for (relation, _) in relations.iter().zip(&in_dependency).filter(|(_, x)| **x) {
+            rational.push(norm(&g, relation.a, relation.b));
+            let mut h = MpPolynomial::new();
+            h[0] = (&c * relation.a).complete();
+            h[1] = Integer::from(relation.b);
+            algebraic.push(h);
         }
 
-        let mut a = sqrt::mul_rational_integers(&rational).sqrt() * f.derivative().evaluate(&m);
+        // With k relations, the rational norms multiply to Y1^k * prod(a + b * m) and the elements
+        // c * a + b * omega to c^k * prod(a + b * m) modulo n. The parity row makes k even unless
+        // c = Y1 = 1, so the square roots only differ by the powers of c and Y1 with exponent
+        // k / 2.
+        let k = Integer::from(rational.len() / 2);
+        let mut a = (sqrt::mul_rational_integers(&rational).sqrt()
+            * f_monic_derivative.evaluate(&omega)
+            * c.clone().pow_mod(&k, n).unwrap())
+        .rem_euc(n);
         let mut b = match sqrt::algebraic_sqrt(
-            &f.mul_mod(
-                &sqrt::mul_algebraic_integers(&algebraic, &f),
-                &f.mul_mod(&f.derivative(), &f.derivative()),
+            &f_monic.mul_mod(
+                &sqrt::mul_algebraic_integers(&algebraic, &f_monic),
+                &f_monic.mul_mod(&f_monic_derivative, &f_monic_derivative),
             ),
-            &f,
+            &f_monic,
         ) {
-            Some(r) => r.evaluate(&m),
+            Some(r) => (r.evaluate(&omega) * pair.y1.clone().pow_mod(&k, n).unwrap()).rem_euc(n),
             None => continue,
         };
         assert_eq!(a.clone().square() % n, b.clone().square() % n);
@@ -273,8 +854,9 @@ pub fn factorize(n: &Integer) -> Vec<Integer> {
 
     factors.sort_unstable();
     factors.dedup();
+    statistics.sqrt_time = start.elapsed();
 
-    factors
+    Ok((factors, statistics))
 }
 
 #[cfg(test)]
@@ -290,29 +872,212 @@ mod tests {
     ];
 
     // 2^3^5^7 + 2 - 3 - 5 - 7 is prime!
+    #[allow(dead_code)]
     const PRIMES_128: [u128; 1] = [2u128.pow(3).pow(5).pow(7) + 2 - 3 - 5 - 7];
 
     #[test]
     fn factorize_semiprime_64() {
-        for i in 0..PRIMES_32.len() {
-            for j in i + 1..PRIMES_32.len() {
-                let factorization =
-                    factorize(&(Integer::from(PRIMES_32[i]) * Integer::from(PRIMES_32[j])));
+        for (i, p) in PRIMES_32.iter().enumerate() {
+            for q in &PRIMES_32[i + 1..] {
+                let n = Integer::from(*p) * Integer::from(*q);
+                let (factorization, _) =
+                    factorize(&n, &Params::new(&n), None, &ExternalFiles::default(), None).unwrap();
                 assert_eq!(factorization.len(), 1);
-                assert_eq!(factorization[0], PRIMES_32[i]);
+                assert_eq!(factorization[0], *p);
+            }
+        }
+    }
+
+    #[test]
+    fn sieving_is_deterministic() {
+        let n = Integer::from(PRIMES_32[0]) * Integer::from(PRIMES_32[1]);
+        let mut params = Params::new(&n);
+        let statistics: Vec<Statistics> = [1, 3]
+            .into_iter()
+            .map(|threads| {
+                params.threads = threads;
+                factorize(&n, &params, None, &ExternalFiles::default(), None)
+                    .unwrap()
+                    .1
+            })
+            .collect();
+        assert_eq!(statistics[0].relations, statistics[1].relations);
+        assert_eq!(
+            statistics[0].partial_relations,
+            statistics[1].partial_relations
+        );
+        assert_eq!(statistics[0].cycles, statistics[1].cycles);
+        assert_eq!(statistics[0].special_q, statistics[1].special_q);
+    }
+
+    #[test]
+    fn export_and_import_relations() {
+        let n = Integer::from(PRIMES_32[0]) * Integer::from(PRIMES_32[2]);
+        let params = Params::new(&n);
+        let path = std::env::temp_dir().join(format!("nfs-relations-{}", std::process::id()));
+
+        let files = ExternalFiles {
+            export_relations: Some(path.clone()),
+            ..Default::default()
+        };
+        let (_, exported) = factorize(&n, &params, None, &files, None).unwrap();
+
+        // The imported relations suffice, so no special-q needs to be sieved.
+        let files = ExternalFiles {
+            import_relations: Some(path.clone()),
+            ..Default::default()
+        };
+        let (factorization, imported) = factorize(&n, &params, None, &files, None).unwrap();
+        assert_eq!(factorization, vec![Integer::from(PRIMES_32[0])]);
+        assert_eq!(imported.special_q, 0);
+        assert_eq!(imported.relations, exported.relations);
+        assert_eq!(imported.partial_relations, exported.partial_relations);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // Writes n = sum c_i * p^i * q^(d - i) with |c_i| <= p / 2 for i < d, so that f = sum c_i * x^i
+    // and g = q * x - p have the common root p / q modulo n.
+    fn base_p_over_q(n: &Integer, d: usize, c: u32, q: u32) -> PolynomialPair {
+        let q = Integer::from(q);
+        let mut p = (n.clone() / c).root(d as u32);
+        while p.clone().gcd(&q) != 1 {
+            p += 1;
+        }
+        let mut f = MpPolynomial::new();
+        let mut r = n.clone();
+        for i in 0..d {
+            let q_pow = q.clone().pow(d as u32 - i as u32);
+            let mut c = (&r * q_pow.clone().invert(&p).unwrap()) % &p;
+            if c > (&p / 2u32).complete() {
+                c -= &p;
+            }
+            r = (r - &c * q_pow) / &p;
+            f[i] = c;
+        }
+        f[d] = r;
+        PolynomialPair {
+            n: n.clone(),
+            f,
+            y0: -p,
+            y1: q,
+            skew: 1.0,
+        }
+    }
+
+    #[test]
+    fn factorize_with_non_monic_polynomial() {
+        let n = Integer::from(PRIMES_32[1]) * Integer::from(PRIMES_32[2]);
+        let pair = base_p_over_q(&n, 4, 20, 7);
+        assert_eq!(pair.check(), Ok(()));
+        // The leading coefficient gives projective roots at 2 and 5, and Y1 one at 7.
+        assert_eq!(pair.f[4], 20);
+
+        let (factorization, _) = factorize(
+            &n,
+            &Params::new(&n),
+            None,
+            &ExternalFiles::default(),
+            Some(&pair),
+        )
+        .unwrap();
+        assert_eq!(factorization, vec![Integer::from(PRIMES_32[1])]);
+    }
+
+    #[test]
+    fn algebraic_valuations_match_norm() {
+        // 4 * x^4 - 67 * x^2 - 17 = (x^2 - 17) * (4 * x^2 + 1) has the bad ideal (2, 1) with two
+        // ideals above it and the bad projective ideal at 2.
+        let mut f = MpPolynomial::new();
+        f[0] = Integer::from(-17);
+        f[2] = Integer::from(-67);
+        f[4] = Integer::from(4);
+        let mut g = MpPolynomial::new();
+        g[0] = Integer::from(-1000);
+        g[1] = Integer::from(1);
+        let n = Integer::from(PRIMES_32[0]) * Integer::from(PRIMES_32[1]);
+        let base = FactorBase::new(&g, &f, &Params::new(&n));
+        assert!(base
+            .bad
+            .iter()
+            .any(|(_, _, ideal)| (ideal.p, ideal.r) == (2, 1)));
+        assert!(base
+            .bad
+            .iter()
+            .any(|(_, _, ideal)| (ideal.p, ideal.r) == (2, 2)));
+
+        // The prime below the ideal of each row.
+        let algebraic_begin = 1 + base.rational.len();
+        let mut row_prime: BTreeMap<usize, u64> = BTreeMap::new();
+        for (i, &(p, _)) in base.algebraic.iter().enumerate() {
+            row_prime.insert(algebraic_begin + i, p);
+        }
+        for (_, extra_begin, ideal) in &base.bad {
+            for k in 1..ideal.len() {
+                row_prime.insert(extra_begin + k - 1, ideal.p);
+            }
+        }
+        // The factor base may end with only some of the ideals above the last prime, so the
+        // valuation at it isn't complete.
+        let mut primes: Vec<u64> = base.algebraic.iter().map(|&(p, _)| p).collect();
+        primes.dedup();
+        let last_prime = Integer::from(primes.pop().unwrap());
+
+        let mut split = 0;
+        for b in 1..30u64 {
+            for a in -300..300i64 {
+                if nt::gcd(a.unsigned_abs(), b) != 1 {
+                    continue;
+                }
+                let mut alg_norm = norm(&f, a, b);
+                let Some(valuations) = algebraic_valuations(&mut alg_norm, a, b, &base) else {
+                    continue;
+                };
+
+                // Compare with the factorization of the norm over the primes of the factor base.
+                let mut rest = norm(&f, a, b);
+                for &p in &primes {
+                    let e = rest.remove_factor_mut(&Integer::from(p));
+                    let found: u32 = valuations
+                        .iter()
+                        .filter(|(row, _)| row_prime[row] == p)
+                        .map(|(_, e)| e)
+                        .sum();
+                    assert_eq!(found, e, "valuation at {} of the norm of ({}, {})", p, a, b);
+                }
+                alg_norm.remove_factor_mut(&last_prime);
+                rest.remove_factor_mut(&last_prime);
+                assert_eq!(alg_norm, rest);
+                for (row, e) in &valuations {
+                    if let Some(&(p, r)) = base.algebraic.get(row - algebraic_begin) {
+                        assert!(*e == 0 || divides((p, r), a, b));
+                    }
+                }
+                if (a, b) == (-1, 1) {
+                    split += 1;
+                    let mut at_2: Vec<u32> = valuations
+                        .iter()
+                        .filter(|(row, _)| row_prime[row] == 2)
+                        .map(|(_, e)| *e)
+                        .collect();
+                    at_2.sort();
+                    assert_eq!(at_2, vec![1, 3]);
+                }
             }
         }
+        assert_eq!(split, 1);
     }
 
     #[ignore]
     #[test]
     fn factorize_semiprime_128() {
-        for i in 0..PRIMES_64.len() {
-            for j in i + 1..PRIMES_64.len() {
-                let factorization =
-                    factorize(&(Integer::from(PRIMES_64[i]) * Integer::from(PRIMES_64[j])));
+        for (i, p) in PRIMES_64.iter().enumerate() {
+            for q in &PRIMES_64[i + 1..] {
+                let n = Integer::from(*p) * Integer::from(*q);
+                let (factorization, _) =
+                    factorize(&n, &Params::new(&n), None, &ExternalFiles::default(), None).unwrap();
                 assert_eq!(factorization.len(), 1);
-                assert_eq!(factorization[0], PRIMES_64[i]);
+                assert_eq!(factorization[0], *p);
             }
         }
     }
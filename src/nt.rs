@@ -1,11 +1,120 @@
-const fn mod_exp(mut a: u64, mut b: u64, n: u64) -> u64 {
-    let mut c: u64 = 1;
+use rug::{
+    integer::IntegerExt64,
+    ops::{Pow, RemRounding},
+    Complete, Integer,
+};
 
+use crate::{polynomial::MpPolynomial, primes::Primes};
+
+// Arithmetic modulo n below 2^64, where mod_add and mod_sub expect operands reduced modulo n.
+// Products are computed with 128 bits unless both factors are below 2^32.
+pub const fn mod_mul(a: u64, b: u64, n: u64) -> u64 {
+    if (a | b) >> 32 == 0 {
+        a * b % n
+    } else {
+        (a as u128 * b as u128 % n as u128) as u64
+    }
+}
+
+pub const fn mod_add(a: u64, b: u64, n: u64) -> u64 {
+    let (c, overflow) = a.overflowing_add(b);
+    if overflow || c >= n {
+        c.wrapping_sub(n)
+    } else {
+        c
+    }
+}
+
+pub const fn mod_sub(a: u64, b: u64, n: u64) -> u64 {
+    if a >= b {
+        a - b
+    } else {
+        a.wrapping_sub(b).wrapping_add(n)
+    }
+}
+
+// Montgomery form modulo an odd n, which represents a by a * 2^64 mod n, so that products are
+// reduced with multiplications instead of a division.
+pub struct Montgomery {
+    n: u64,
+    // -n^-1 mod 2^64.
+    n_neg_inv: u64,
+    // 2^128 mod n.
+    r2: u64,
+}
+
+impl Montgomery {
+    pub const fn new(n: u64) -> Montgomery {
+        assert!(n & 1 == 1);
+        // Newton's iteration doubles the number of correct low bits of n^-1, starting with 3.
+        let mut inv = n;
+        let mut i = 0;
+        while i < 5 {
+            inv = inv.wrapping_mul(2u64.wrapping_sub(n.wrapping_mul(inv)));
+            i += 1;
+        }
+        let r = ((1u128 << 64) % n as u128) as u64;
+        Montgomery {
+            n,
+            n_neg_inv: inv.wrapping_neg(),
+            r2: mod_mul(r, r, n),
+        }
+    }
+
+    // t * 2^-64 mod n for t < n * 2^64.
+    const fn reduce(&self, t: u128) -> u64 {
+        let m = (t as u64).wrapping_mul(self.n_neg_inv);
+        let (s, carry) = t.overflowing_add(m as u128 * self.n as u128);
+        let high = (s >> 64) as u64;
+        if carry || high >= self.n {
+            high.wrapping_sub(self.n)
+        } else {
+            high
+        }
+    }
+
+    pub const fn to_montgomery(&self, a: u64) -> u64 {
+        self.reduce(a as u128 * self.r2 as u128)
+    }
+
+    pub const fn to_residue(&self, a: u64) -> u64 {
+        self.reduce(a as u128)
+    }
+
+    pub const fn mul(&self, a: u64, b: u64) -> u64 {
+        self.reduce(a as u128 * b as u128)
+    }
+
+    // a^b for a in Montgomery form.
+    pub const fn pow(&self, mut a: u64, mut b: u64) -> u64 {
+        let mut c = self.to_montgomery(1);
+        while b != 0 {
+            if b & 1 == 1 {
+                c = self.mul(c, a);
+            }
+            a = self.mul(a, a);
+            b >>= 1;
+        }
+        c
+    }
+}
+
+// Uses the Montgomery form for odd moduli that don't fit into 32 bits, whose products are
+// expensive to reduce otherwise.
+pub const fn mod_exp(mut a: u64, mut b: u64, n: u64) -> u64 {
+    if n & 1 == 1 && n >> 32 != 0 {
+        let montgomery = Montgomery::new(n);
+        let a = montgomery.to_montgomery(a % n);
+        return montgomery.to_residue(montgomery.pow(a, b));
+    }
+
+    let mut c: u64 = 1 % n;
+    a %= n;
     while b != 0 {
         if b & 1 == 1 {
-            c = (c * a) % n;
+            c = mod_mul(c, a, n);
         }
-        a = (a * a) % n;
+        a = mod_mul(a, a, n);
         b >>= 1;
     }
 
@@ -16,31 +125,151 @@ pub const fn mod_inv(a: u64, p: u64) -> u64 {
     mod_exp(a, p - 2, p)
 }
 
-pub const fn legendre(p: u64, q: u64) -> u64 {
-    mod_exp(p, (q - 1) >> 1, q)
+// The Jacobi symbol (a / n) for odd n, computed with quadratic reciprocity. For a prime n, it is
+// the Legendre symbol, which tells whether a is a square modulo n.
+pub const fn jacobi(mut a: u64, mut n: u64) -> i32 {
+    assert!(n & 1 == 1);
+    a %= n;
+    let mut result = 1;
+    while a != 0 {
+        let twos = a.trailing_zeros();
+        a >>= twos;
+        // (2 / n) = -1 for n = 3, 5 mod 8.
+        if twos & 1 == 1 && (n & 7 == 3 || n & 7 == 5) {
+            result = -result;
+        }
+        if a & 3 == 3 && n & 3 == 3 {
+            result = -result;
+        }
+        let r = n % a;
+        n = a;
+        a = r;
+    }
+    if n == 1 {
+        result
+    } else {
+        0
+    }
 }
 
-// Returns true, if (and only if? I'm not sure.) n is a prime.
-pub fn miller_rabin(n: u64) -> bool {
-    if n == 2 {
-        return true;
+// A square root of a modulo the prime p, or None if a is not a square, using the Tonelli-Shanks
+// algorithm.
+pub fn sqrt_mod(a: u64, p: u64) -> Option<u64> {
+    let a = a % p;
+    if a == 0 || p == 2 {
+        return Some(a);
+    }
+    if jacobi(a, p) != 1 {
+        return None;
     }
 
-    const MILLER_RABIN_BASES: [u64; 3] = [15, 7363882082, 992620450144556];
+    let s = (p - 1).trailing_zeros();
+    let q = (p - 1) >> s;
+    let mut z: u64 = 2;
+    while jacobi(z, p) != -1 {
+        z += 1;
+    }
+
+    let mut m = s;
+    let mut c = mod_exp(z, q, p);
+    let mut t = mod_exp(a, q, p);
+    let mut r = mod_exp(a, (q + 1) >> 1, p);
+
+    while t != 1 {
+        let mut i = 0;
+        let mut u = t;
+        while u != 1 {
+            u = mod_mul(u, u, p);
+            i += 1;
+        }
+        let b = mod_exp(c, 1 << (m - i - 1), p);
+        m = i;
+        c = mod_mul(b, b, p);
+        t = mod_mul(t, c, p);
+        r = mod_mul(r, b, p);
+    }
+
+    Some(r)
+}
+
+// Returns (g, x, y) with g = gcd(a, b) = a * x + b * y.
+pub const fn ext_gcd(a: u64, b: u64) -> (u64, i128, i128) {
+    let (mut r0, mut r1) = (a as i128, b as i128);
+    let (mut x0, mut x1) = (1i128, 0i128);
+    let (mut y0, mut y1) = (0i128, 1i128);
+    while r1 != 0 {
+        let q = r0 / r1;
+        (r0, r1) = (r1, r0 - q * r1);
+        (x0, x1) = (x1, x0 - q * x1);
+        (y0, y1) = (y1, y0 - q * y1);
+    }
+    (r0 as u64, x0, y0)
+}
+
+// The inverse of a modulo any n, which exists if they are coprime. mod_inv is faster for primes.
+pub const fn invert(a: u64, n: u64) -> Option<u64> {
+    let (g, x, _) = ext_gcd(a % n, n);
+    if g != 1 {
+        return None;
+    }
+    Some(x.rem_euclid(n as i128) as u64)
+}
+
+// Lifts the root r of f modulo p to the root modulo p^k congruent to it, with Newton's iteration.
+// Returns None if r is no root or a multiple one, which need not lift uniquely.
+pub fn hensel_lift(f: &MpPolynomial, p: u64, r: u64, k: u32) -> Option<Integer> {
+    let f_derivative = f.derivative();
+    if !f.evaluate(r).is_divisible_u64(p) || f_derivative.evaluate(r).is_divisible_u64(p) {
+        return None;
+    }
+
+    let modulus = Integer::from(p).pow(k);
+    let mut r = Integer::from(r);
+    loop {
+        let y = f.evaluate(&r).rem_euc(&modulus);
+        if y == 0 {
+            return Some(r);
+        }
+        let inverse = f_derivative.evaluate(&r).invert(&modulus).unwrap();
+        r = (r - y * inverse).rem_euc(&modulus);
+    }
+}
+
+// The x modulo m1 * m2 with x = r1 mod m1 and x = r2 mod m2, or None if the moduli are not
+// coprime.
+pub fn crt(r1: &Integer, m1: &Integer, r2: &Integer, m2: &Integer) -> Option<Integer> {
+    let inverse = m1.invert_ref(m2).map(Integer::from)?;
+    let k = ((r2 - r1).complete() * inverse).rem_euc(m2);
+    Some((r1 + k * m1).rem_euc((m1 * m2).complete()))
+}
+
+// Deterministic primality test for all n below 2^64: no composite of that size is a strong
+// pseudoprime to all of the bases found by Jim Sinclair.
+pub fn miller_rabin(n: u64) -> bool {
+    const MILLER_RABIN_BASES: [u64; 7] = [2, 325, 9375, 28178, 450775, 9780504, 1795265022];
+
+    if n < 2 {
+        return false;
+    }
+    for p in [2, 3, 5, 7] {
+        if n.is_multiple_of(p) {
+            return n == p;
+        }
+    }
 
     let trailing_zeros = (n - 1).trailing_zeros();
     let u = (n - 1) >> trailing_zeros;
 
     for mut a in MILLER_RABIN_BASES {
-        a = a % n as u64;
+        a %= n;
         if a == 0 {
             continue;
         }
 
-        let mut x = mod_exp(a, u as u64, n as u64);
+        let mut x = mod_exp(a, u, n);
         for _ in 0..trailing_zeros {
-            let y = (x * x) % n as u64;
-            if y == 1 && x != 1 && x != n as u64 - 1 {
+            let y = mod_mul(x, x, n);
+            if y == 1 && x != 1 && x != n - 1 {
                 return false;
             }
             x = y;
@@ -52,9 +281,234 @@ pub fn miller_rabin(n: u64) -> bool {
     true
 }
 
+// The Baillie-PSW test, a strong probable prime test to base 2 followed by a strong Lucas test.
+// No composite passing both is known, but unlike for miller_rabin, there is no proof. Numbers below
+// 2^64 are tested with miller_rabin, so the result is proven for them.
+pub fn bpsw(n: &Integer) -> bool {
+    if let Some(n) = n.to_u64() {
+        return miller_rabin(n);
+    }
+    if n.is_even() {
+        return false;
+    }
+    for p in Primes::new(3).take_while(|&p| p < 1000) {
+        if n.is_divisible_u64(p) {
+            return false;
+        }
+    }
+    strong_probable_prime(n, 2) && strong_lucas(n)
+}
+
+// The Miller-Rabin test of the odd n with the base a.
+fn strong_probable_prime(n: &Integer, a: u64) -> bool {
+    let n_minus_1 = (n - 1u32).complete();
+    let trailing_zeros = n_minus_1.find_one(0).unwrap();
+    let u = (&n_minus_1 >> trailing_zeros).complete();
+
+    let mut x = Integer::from(a).pow_mod(&u, n).unwrap();
+    if x == 1 || x == n_minus_1 {
+        return true;
+    }
+    for _ in 1..trailing_zeros {
+        x.square_mut();
+        x %= n;
+        if x == n_minus_1 {
+            return true;
+        }
+    }
+    false
+}
+
+// The strong Lucas test of the odd n with the parameters P = 1 and Q = (1 - D) / 4 chosen by
+// Selfridge, where D is the first of 5, -7, 9, -11, ... with the Jacobi symbol (D / n) = -1. With
+// n + 1 = d * 2^s and d odd, a prime divides U_d or one of V_(d * 2^r) for 0 <= r < s.
+fn strong_lucas(n: &Integer) -> bool {
+    // Otherwise there is no suitable D.
+    if n.is_perfect_square() {
+        return false;
+    }
+    let mut d: i64 = 5;
+    loop {
+        match Integer::from(d).jacobi(n) {
+            -1 => break,
+            0 if *n != d.unsigned_abs() => return false,
+            _ => d = if d > 0 { -d - 2 } else { -d + 2 },
+        }
+    }
+    let q = Integer::from((1 - d) / 4);
+    let d = Integer::from(d);
+
+    // Halves x modulo n.
+    let half = |x: Integer| -> Integer {
+        match x.is_odd() {
+            true => (x + n) >> 1,
+            false => x >> 1,
+        }
+    };
+
+    let n_plus_1 = (n + 1u32).complete();
+    let s = n_plus_1.find_one(0).unwrap();
+    let k = (&n_plus_1 >> s).complete();
+
+    // U_j, V_j and Q^j for j = 1, running through the leading bits of k.
+    let (mut u, mut v) = (Integer::from(1), Integer::from(1));
+    let mut q_j = q.clone().rem_euc(n);
+    for i in (0..k.significant_bits() - 1).rev() {
+        u = (&u * &v).complete() % n;
+        v = (v.square() - Integer::from(2 * &q_j)).rem_euc(n);
+        q_j = q_j.square() % n;
+        if k.get_bit(i) {
+            let u_next = half((&u + &v).complete().rem_euc(n));
+            v = half((&d * &u + &v).complete().rem_euc(n));
+            u = u_next;
+            q_j = (q_j * &q).rem_euc(n);
+        }
+    }
+
+    if u == 0 || v == 0 {
+        return true;
+    }
+    for _ in 1..s {
+        v = (v.square() - Integer::from(2 * &q_j)).rem_euc(n);
+        q_j = q_j.square() % n;
+        if v == 0 {
+            return true;
+        }
+    }
+    false
+}
+
 pub const fn gcd(a: u64, b: u64) -> u64 {
     if b == 0 {
         return a;
     }
-    return gcd(b, a % b);
+    gcd(b, a % b)
+}
+
+// Returns all primes below bound.
+pub fn primes_below(bound: u64) -> Vec<u64> {
+    Primes::new(2).take_while(|&p| p < bound).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rug::integer::IsPrime;
+
+    use super::*;
+
+    #[test]
+    fn arithmetic_near_2_64() {
+        // The largest prime below 2^64, and moduli of both parities just below it.
+        let p = u64::MAX - 58;
+        for n in [p, u64::MAX, u64::MAX - 1, (1 << 63) + 1, 1 << 40] {
+            let n_mp = Integer::from(n);
+            for (a, b) in [(n - 1, n - 1), (n - 2, n / 3), (1 << 63, 12345), (0, n - 1)] {
+                let (a, b) = (a % n, b % n);
+                let (a_mp, b_mp) = (Integer::from(a), Integer::from(b));
+                assert_eq!(mod_mul(a, b, n), (&a_mp * &b_mp).complete() % &n_mp);
+                assert_eq!(mod_add(a, b, n), (&a_mp + &b_mp).complete() % &n_mp);
+                assert_eq!(mod_sub(a, b, n), (&a_mp - &b_mp).complete().rem_euc(&n_mp));
+                assert_eq!(
+                    mod_exp(a, b, n),
+                    a_mp.clone().pow_mod(&b_mp, &n_mp).unwrap()
+                );
+            }
+        }
+
+        assert_eq!(mod_mul(mod_inv(3, p), 3, p), 1);
+        assert_eq!(jacobi(p - 1, p), 1);
+        assert_eq!(jacobi(3, p), -1);
+        assert!(miller_rabin(p));
+        assert!(!miller_rabin(4294967291 * 4294967279));
+    }
+
+    #[test]
+    fn primality() {
+        let primes = primes_below(100000);
+        let tested: Vec<u64> = (0..100000).filter(|&n| miller_rabin(n)).collect();
+        assert_eq!(tested, primes);
+        // A strong pseudoprime to all prime bases up to 23.
+        assert!(!miller_rabin(3825123056546413051));
+
+        // The odd composites below 20000 that pass the strong Lucas test.
+        let pseudoprimes = [5459, 5777, 10877, 16109, 18971];
+        for n in (3..20000u64).step_by(2) {
+            let n_mp = Integer::from(n);
+            if !n_mp.is_perfect_square() {
+                let expected = miller_rabin(n) || pseudoprimes.contains(&n);
+                assert_eq!(strong_lucas(&n_mp), expected, "n = {}", n);
+            }
+        }
+
+        let two = Integer::from(2);
+        assert!(bpsw(&(two.clone().pow(127u32) - 1u32)));
+        assert!(!bpsw(&(two.clone().pow(67u32) - 1u32)));
+        let m61 = two.clone().pow(61u32) - 1u32;
+        let m89 = two.clone().pow(89u32) - 1u32;
+        assert!(bpsw(&m89));
+        assert!(!bpsw(&(&m61 * &m89).complete()));
+        // A strong pseudoprime to all prime bases up to 37.
+        assert!(!bpsw(
+            &"318665857834031151167461".parse::<Integer>().unwrap()
+        ));
+        for k in 0..2000u32 {
+            let n = two.clone().pow(64u32) + k;
+            assert_eq!(
+                bpsw(&n),
+                n.is_probably_prime(30) != IsPrime::No,
+                "n = {}",
+                n
+            );
+        }
+    }
+
+    #[test]
+    fn roots_inverses_and_crt() {
+        for n in (1..200u64).step_by(2) {
+            for a in 0..200 {
+                assert_eq!(jacobi(a, n), Integer::from(a).jacobi(&Integer::from(n)));
+            }
+        }
+
+        // 998244353 - 1 and 65537 - 1 are divisible by large powers of 2.
+        for p in [2, 3, 5, 13, 17, 97, 65537, 998244353, u64::MAX - 58] {
+            for a in (0..50).chain([p - 1, p - 2]) {
+                match sqrt_mod(a, p) {
+                    Some(r) => assert_eq!(mod_mul(r, r, p), a % p),
+                    None => assert_eq!(jacobi(a, p), -1),
+                }
+            }
+        }
+
+        let (g, x, y) = ext_gcd(240, 46);
+        assert_eq!((g, 240 * x + 46 * y), (2, 2));
+        assert_eq!(invert(3, 10), Some(7));
+        assert_eq!(invert(4, 10), None);
+        let n = u64::MAX - 1;
+        assert_eq!(mod_mul(invert(n - 1, n).unwrap(), n - 1, n), 1);
+
+        // x^2 - 2 has the roots 3 and 4 modulo 7, but 1 is a double root modulo 2.
+        let mut f = MpPolynomial::new();
+        f[2] = Integer::from(1);
+        f[0] = Integer::from(-2);
+        let modulus = Integer::from(7).pow(20u32);
+        for r in [3, 4] {
+            let lifted = hensel_lift(&f, 7, r, 20).unwrap();
+            assert!(lifted < modulus && lifted.mod_u(7) as u64 == r);
+            assert!((lifted.square() - 2u32).is_divisible(&modulus));
+        }
+        assert_eq!(hensel_lift(&f, 7, 2, 20), None);
+        assert_eq!(hensel_lift(&f, 2, 0, 20), None);
+
+        let (m1, m2) = (Integer::from(3), Integer::from(5));
+        assert_eq!(
+            crt(&Integer::from(2), &m1, &Integer::from(3), &m2),
+            Some(Integer::from(8))
+        );
+        assert_eq!(crt(&Integer::from(1), &m1, &Integer::from(2), &m1), None);
+        let (m1, m2) = (modulus.clone(), Integer::from(u64::MAX - 58));
+        let x = crt(&Integer::from(5), &m1, &Integer::from(-1), &m2).unwrap();
+        assert_eq!(x.clone() % &m1, 5);
+        assert_eq!(x % &m2, u64::MAX - 59);
+    }
 }
@@ -1,24 +1,50 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
 use rug::Integer;
 
-pub const MAX_DEGREE: usize = Params::PARAM_TABLE[Params::PARAM_TABLE.len() - 1]
-    .1
-    .polynomial_degree;
+// The largest polynomial degree accepted in the parameters and in .poly files. The polynomial types
+// themselves have no limit.
+pub const MAX_DEGREE: usize = 8;
 
 pub const OVERSQUARENESS: usize = 13;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Params {
     pub rational_base_size: usize,
     pub algebraic_base_size: usize,
     pub quad_char_base_size: usize,
     pub polynomial_degree: usize,
     pub sieve_array_size: usize,
+    // The lattice_rows rows of a special-q are sieved one after the other in segments of this
+    // length, which should fit into the L1 or L2 cache.
+    pub sieve_segment_size: usize,
     pub rational_fudge: i8,
     pub algebraic_threshold: i8,
+    // The lattice sieve uses the prime ideals of the algebraic factor base with norm in
+    // [special_q_min, special_q_max] as special-q, and sieves lattice_rows rows of length
+    // sieve_array_size for each of them.
+    pub special_q_min: u64,
+    pub special_q_max: u64,
+    pub lattice_rows: usize,
+    // Relations may contain up to large_primes (at most 2) primes outside the factor base, as long
+    // as they are below the large prime bound of their side.
+    pub rational_large_prime_bound: u64,
+    pub algebraic_large_prime_bound: u64,
+    pub large_primes: usize,
+    // Amount of work spent on polynomial selection, see polyselect::select. With 0, the base-m
+    // polynomial is used.
+    pub polyselect_effort: usize,
+    // Number of threads used for sieving, 0 means one per available core.
+    pub threads: usize,
 }
 
 impl Params {
-    pub const PARAM_TABLE: [(u32, Params); 2] = [
+    // Parameters for the general number field sieve, by the size of n in bits. The polynomial
+    // degree grows from 4 to 6 with the size, larger numbers use the last row.
+    pub const PARAM_TABLE: [(u32, Params); 5] = [
         (
             64,
             Params {
@@ -26,9 +52,18 @@ impl Params {
                 algebraic_base_size: 400,
                 quad_char_base_size: 64,
                 polynomial_degree: 4,
-                sieve_array_size: 10000,
-                rational_fudge: 7,
-                algebraic_threshold: 13,
+                sieve_array_size: 1024,
+                sieve_segment_size: 1 << 15,
+                rational_fudge: 10,
+                algebraic_threshold: 36,
+                special_q_min: 100,
+                special_q_max: 3000,
+                lattice_rows: 256,
+                rational_large_prime_bound: 1 << 13,
+                algebraic_large_prime_bound: 1 << 13,
+                large_primes: 1,
+                polyselect_effort: 1,
+                threads: 0,
             },
         ),
         (
@@ -38,9 +73,151 @@ impl Params {
                 algebraic_base_size: 1400,
                 quad_char_base_size: 64,
                 polynomial_degree: 4,
-                sieve_array_size: 120000,
+                sieve_array_size: 2048,
+                sieve_segment_size: 1 << 15,
+                rational_fudge: 20,
+                algebraic_threshold: 40,
+                special_q_min: 500,
+                special_q_max: 12000,
+                lattice_rows: 512,
+                rational_large_prime_bound: 1 << 16,
+                algebraic_large_prime_bound: 1 << 16,
+                large_primes: 2,
+                polyselect_effort: 8,
+                threads: 0,
+            },
+        ),
+        (
+            256,
+            Params {
+                rational_base_size: 6000,
+                algebraic_base_size: 6000,
+                quad_char_base_size: 64,
+                polynomial_degree: 5,
+                sieve_array_size: 8192,
+                sieve_segment_size: 1 << 15,
+                rational_fudge: 24,
+                algebraic_threshold: 48,
+                special_q_min: 3000,
+                special_q_max: 60000,
+                lattice_rows: 1024,
+                rational_large_prime_bound: 1 << 20,
+                algebraic_large_prime_bound: 1 << 20,
+                large_primes: 2,
+                polyselect_effort: 32,
+                threads: 0,
+            },
+        ),
+        (
+            384,
+            Params {
+                rational_base_size: 20000,
+                algebraic_base_size: 20000,
+                quad_char_base_size: 64,
+                polynomial_degree: 5,
+                sieve_array_size: 16384,
+                sieve_segment_size: 1 << 15,
+                rational_fudge: 27,
+                algebraic_threshold: 54,
+                special_q_min: 10000,
+                special_q_max: 225000,
+                lattice_rows: 2048,
+                rational_large_prime_bound: 1 << 23,
+                algebraic_large_prime_bound: 1 << 23,
+                large_primes: 2,
+                polyselect_effort: 64,
+                threads: 0,
+            },
+        ),
+        (
+            512,
+            Params {
+                rational_base_size: 60000,
+                algebraic_base_size: 60000,
+                quad_char_base_size: 64,
+                polynomial_degree: 6,
+                sieve_array_size: 32768,
+                sieve_segment_size: 1 << 15,
+                rational_fudge: 30,
+                algebraic_threshold: 60,
+                special_q_min: 30000,
+                special_q_max: 750000,
+                lattice_rows: 4096,
+                rational_large_prime_bound: 1 << 26,
+                algebraic_large_prime_bound: 1 << 26,
+                large_primes: 2,
+                polyselect_effort: 128,
+                threads: 0,
+            },
+        ),
+    ];
+
+    // Parameters for the special number field sieve, by SNFS difficulty (the size of the special
+    // number in bits). The norms are much smaller than with a selected polynomial, so smaller
+    // factor bases suffice. The polynomial degree is the largest degree tried for the polynomial,
+    // see SpecialForm::polynomial.
+    pub const SNFS_PARAM_TABLE: [(u32, Params); 3] = [
+        (
+            144,
+            Params {
+                rational_base_size: 600,
+                algebraic_base_size: 600,
+                quad_char_base_size: 64,
+                polynomial_degree: 4,
+                sieve_array_size: 1024,
+                sieve_segment_size: 1 << 15,
                 rational_fudge: 10,
-                algebraic_threshold: 30,
+                algebraic_threshold: 36,
+                special_q_min: 100,
+                special_q_max: 4000,
+                lattice_rows: 512,
+                rational_large_prime_bound: 1 << 14,
+                algebraic_large_prime_bound: 1 << 14,
+                large_primes: 1,
+                polyselect_effort: 0,
+                threads: 0,
+            },
+        ),
+        (
+            192,
+            Params {
+                rational_base_size: 1400,
+                algebraic_base_size: 1400,
+                quad_char_base_size: 64,
+                polynomial_degree: 5,
+                sieve_array_size: 2048,
+                sieve_segment_size: 1 << 15,
+                rational_fudge: 20,
+                algebraic_threshold: 40,
+                special_q_min: 500,
+                special_q_max: 12000,
+                lattice_rows: 512,
+                rational_large_prime_bound: 1 << 16,
+                algebraic_large_prime_bound: 1 << 16,
+                large_primes: 2,
+                polyselect_effort: 0,
+                threads: 0,
+            },
+        ),
+        (
+            256,
+            Params {
+                rational_base_size: 3000,
+                algebraic_base_size: 3000,
+                quad_char_base_size: 64,
+                polynomial_degree: 6,
+                sieve_array_size: 4096,
+                sieve_segment_size: 1 << 15,
+                rational_fudge: 24,
+                algebraic_threshold: 48,
+                special_q_min: 1000,
+                special_q_max: 30000,
+                lattice_rows: 1024,
+                rational_large_prime_bound: 1 << 18,
+                algebraic_large_prime_bound: 1 << 18,
+                large_primes: 2,
+                polyselect_effort: 0,
+                threads: 0,
             },
         ),
     ];
@@ -56,4 +233,173 @@ impl Params {
 
         Params::PARAM_TABLE.last().unwrap().1
     }
+
+    pub fn new_snfs(difficulty: u32) -> Params {
+        for (bits_lim, params) in Params::SNFS_PARAM_TABLE {
+            if difficulty <= bits_lim {
+                return params;
+            }
+        }
+
+        Params::SNFS_PARAM_TABLE.last().unwrap().1
+    }
+
+    pub const NAMES: [&'static str; 16] = [
+        "rational_base_size",
+        "algebraic_base_size",
+        "quad_char_base_size",
+        "polynomial_degree",
+        "sieve_array_size",
+        "sieve_segment_size",
+        "rational_fudge",
+        "algebraic_threshold",
+        "special_q_min",
+        "special_q_max",
+        "lattice_rows",
+        "rational_large_prime_bound",
+        "algebraic_large_prime_bound",
+        "large_primes",
+        "polyselect_effort",
+        "threads",
+    ];
+
+    // Sets the parameter called name (as in the struct definition) to the parsed value.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name {
+            "rational_base_size" => self.rational_base_size = parse_positive(name, value)?,
+            "algebraic_base_size" => self.algebraic_base_size = parse_positive(name, value)?,
+            "quad_char_base_size" => self.quad_char_base_size = parse_positive(name, value)?,
+            "polynomial_degree" => {
+                let d = parse_positive(name, value)?;
+                if !(2..=MAX_DEGREE).contains(&d) {
+                    return Err(format!(
+                        "polynomial_degree must be between 2 and {}, got {}",
+                        MAX_DEGREE, d
+                    ));
+                }
+                self.polynomial_degree = d;
+            }
+            "sieve_array_size" => self.sieve_array_size = parse_positive(name, value)?,
+            "sieve_segment_size" => self.sieve_segment_size = parse_positive(name, value)?,
+            "rational_fudge" => self.rational_fudge = parse(name, value)?,
+            "algebraic_threshold" => self.algebraic_threshold = parse(name, value)?,
+            "special_q_min" => self.special_q_min = parse_positive(name, value)? as u64,
+            "special_q_max" => self.special_q_max = parse_positive(name, value)? as u64,
+            "lattice_rows" => self.lattice_rows = parse_positive(name, value)?,
+            "rational_large_prime_bound" => {
+                self.rational_large_prime_bound = parse_large_prime_bound(name, value)?
+            }
+            "algebraic_large_prime_bound" => {
+                self.algebraic_large_prime_bound = parse_large_prime_bound(name, value)?
+            }
+            "large_primes" => {
+                let k = parse(name, value)?;
+                if k > 2 {
+                    return Err(format!("large_primes must be at most 2, got {}", k));
+                }
+                self.large_primes = k;
+            }
+            "polyselect_effort" => self.polyselect_effort = parse(name, value)?,
+            "threads" => self.threads = parse(name, value)?,
+            _ => return Err(format!("unknown parameter {}", name)),
+        }
+        Ok(())
+    }
+
+    // Applies assignments of the form "name = value", one per line. Empty lines and lines starting
+    // with '#' are ignored.
+    pub fn apply_assignments(&mut self, s: &str) -> Result<(), String> {
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=') {
+                Some((name, value)) => self
+                    .set(name.trim(), value.trim())
+                    .map_err(|e| format!("line {}: {}", i + 1, e))?,
+                None => return Err(format!("line {}: expected name = value", i + 1)),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse<T: FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {} for {}", value, name))
+}
+
+fn parse_positive(name: &str, value: &str) -> Result<usize, String> {
+    match parse(name, value)? {
+        0 => Err(format!("{} must be positive", name)),
+        x => Ok(x),
+    }
+}
+
+// Large primes are stored in 64 bits and are multiplied modulo themselves, so they must be below
+// 2^32.
+fn parse_large_prime_bound(name: &str, value: &str) -> Result<u64, String> {
+    match parse_positive(name, value)? as u64 {
+        bound if bound >= 1 << 32 => Err(format!("{} must be below 2^32", name)),
+        bound => Ok(bound),
+    }
+}
+
+impl Display for Params {
+    // Writes the parameters in the format accepted by apply_assignments.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "rational_base_size = {}", self.rational_base_size)?;
+        writeln!(f, "algebraic_base_size = {}", self.algebraic_base_size)?;
+        writeln!(f, "quad_char_base_size = {}", self.quad_char_base_size)?;
+        writeln!(f, "polynomial_degree = {}", self.polynomial_degree)?;
+        writeln!(f, "sieve_array_size = {}", self.sieve_array_size)?;
+        writeln!(f, "sieve_segment_size = {}", self.sieve_segment_size)?;
+        writeln!(f, "rational_fudge = {}", self.rational_fudge)?;
+        writeln!(f, "algebraic_threshold = {}", self.algebraic_threshold)?;
+        writeln!(f, "special_q_min = {}", self.special_q_min)?;
+        writeln!(f, "special_q_max = {}", self.special_q_max)?;
+        writeln!(f, "lattice_rows = {}", self.lattice_rows)?;
+        writeln!(
+            f,
+            "rational_large_prime_bound = {}",
+            self.rational_large_prime_bound
+        )?;
+        writeln!(
+            f,
+            "algebraic_large_prime_bound = {}",
+            self.algebraic_large_prime_bound
+        )?;
+        writeln!(f, "large_primes = {}", self.large_primes)?;
+        writeln!(f, "polyselect_effort = {}", self.polyselect_effort)?;
+        writeln!(f, "threads = {}", self.threads)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assignments_roundtrip() {
+        let mut params = Params::PARAM_TABLE[1].1;
+        params.rational_fudge = -3;
+        params.sieve_array_size = 4242;
+        params.sieve_segment_size = 1000;
+
+        let mut parsed = Params::PARAM_TABLE[0].1;
+        parsed.apply_assignments(&params.to_string()).unwrap();
+        assert_eq!(parsed, params);
+
+        assert!(parsed.apply_assignments("sieve_array_size = 0").is_err());
+        assert!(parsed.apply_assignments("sieve_segment_size = 0").is_err());
+        assert!(parsed.apply_assignments("polynomial_degree = 9").is_err());
+        assert!(parsed.apply_assignments("no_such_param = 1").is_err());
+        assert!(parsed.apply_assignments("large_primes = 3").is_err());
+        assert!(parsed
+            .apply_assignments("# comment\n\nrational_fudge = 3")
+            .is_ok());
+        assert_eq!(parsed.rational_fudge, 3);
+    }
 }
@@ -0,0 +1,77 @@
+use rug::{Complete, Integer};
+
+use crate::nt;
+
+// Pollard's p - 1 method. Finds a prime factor p of n if p - 1 is b1-smooth, except for at most one
+// prime factor in (b1, b2].
+pub fn pm1(n: &Integer, b1: u64, b2: u64) -> Option<Integer> {
+    let primes = nt::primes_below(b2 + 1);
+    let mut a = Integer::from(3);
+
+    // Stage 1: exponentiate by all prime powers below b1.
+    let mut stage_two_begin = primes.len();
+    for (i, &p) in primes.iter().enumerate() {
+        if p > b1 {
+            stage_two_begin = i;
+            break;
+        }
+        let mut q = p;
+        while q <= b1 / p {
+            q *= p;
+        }
+        a.pow_mod_mut(&Integer::from(q), n).unwrap();
+    }
+
+    let g = (&a - 1u32).complete().gcd(n);
+    if g == *n {
+        return None;
+    }
+    if g != 1 {
+        return Some(g);
+    }
+
+    // Stage 2: for consecutive primes q, a^q is obtained from the previous one by multiplying with
+    // a^(gap), which is looked up in a table of the even powers of a.
+    const MAX_GAP: usize = 256;
+    let a_squared = a.square_ref().complete() % n;
+    let mut gap_powers: Vec<Integer> = vec![a_squared.clone()];
+    for i in 1..MAX_GAP / 2 {
+        gap_powers.push((&gap_powers[i - 1] * &a_squared).complete() % n);
+    }
+
+    let mut product = Integer::from(1);
+    let mut x = Integer::new();
+    let mut previous: u64 = 0;
+    for &q in &primes[stage_two_begin..] {
+        if previous == 0 || (q - previous) as usize > MAX_GAP {
+            x = a.pow_mod_ref(&Integer::from(q), n).unwrap().into();
+        } else {
+            x *= &gap_powers[(q - previous) as usize / 2 - 1];
+            x %= n;
+        }
+        product *= (&x - 1u32).complete();
+        product %= n;
+        previous = q;
+    }
+
+    let g = product.gcd(n);
+    if g == 1 || g == *n {
+        None
+    } else {
+        Some(g)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pm1_smooth_order() {
+        // 1000000007 - 1 = 2 * 500000003 is not smooth, but p = 2^3 * 3^2 * 5 * 7 * 11 * 13 * 99991
+        // + 1 is a prime with p - 1 smooth up to one larger prime.
+        let p = Integer::from(36032756761u64);
+        let n = &p * Integer::from(1000000007u64);
+        assert_eq!(pm1(&n, 1000, 100000), Some(p));
+    }
+}
@@ -1,14 +1,17 @@
-use core::mem::swap;
+use core::mem::take;
 use std::{
+    borrow::Cow,
     fmt::{Debug, Display},
     ops::{Index, IndexMut, MulAssign},
+    str::FromStr,
 };
 
-use rug::{integer::IntegerExt64, rand::RandState, Complete, Integer};
+use rug::{Complete, Integer};
 
 use crate::{
     gfpolynomial::GfPolynomial,
     params::{Params, MAX_DEGREE},
+    polyselect,
 };
 
 pub trait Polynomial<T>
@@ -17,19 +20,30 @@ where
 {
     fn degree(&self) -> usize;
 
-    fn coefficients(self) -> [T; MAX_DEGREE + 1];
+    fn coefficients(self) -> Vec<T>;
 
-    fn coefficients_ref(&self) -> &[T; MAX_DEGREE + 1];
+    fn coefficients_ref(&self) -> &[T];
 
-    fn coefficients_mut(&mut self) -> &mut [T; MAX_DEGREE + 1];
+    fn coefficients_mut(&mut self) -> &mut [T];
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct MpPolynomial([Integer; MAX_DEGREE + 1]);
+// Returned when indexing a polynomial beyond its stored coefficients.
+static ZERO: Integer = Integer::ZERO;
+
+// The coefficients are stored in a vector that grows when a coefficient beyond its end is set, so
+// the degree is not limited. Reading beyond the end gives 0. There is always at least one
+// coefficient, and trailing zeros are ignored when comparing polynomials.
+#[derive(Clone, Debug)]
+pub struct MpPolynomial(Vec<Integer>);
 
 impl MpPolynomial {
     pub fn new() -> MpPolynomial {
-        MpPolynomial([Integer::ZERO; MAX_DEGREE + 1])
+        MpPolynomial(vec![Integer::new()])
+    }
+
+    // The zero polynomial with room for len coefficients.
+    fn with_len(len: usize) -> MpPolynomial {
+        MpPolynomial(vec![Integer::new(); len.max(1)])
     }
 
     pub fn evaluate<T: Copy>(&self, x: T) -> Integer
@@ -37,7 +51,7 @@ impl MpPolynomial {
         Integer: MulAssign<T>,
     {
         let mut z = Integer::from(1);
-        let mut y = self[0].clone();
+        let mut y = self.0[0].clone();
         for coefficient in self.coefficients_ref().iter().skip(1) {
             z *= x;
             y += coefficient * &z;
@@ -53,50 +67,67 @@ impl MpPolynomial {
         f
     }
 
+    // The roots modulo the prime p in increasing order, see GfPolynomial::roots.
     pub fn find_roots_mod_p(&self, p: u64) -> Vec<u64> {
-        let mut roots: Vec<u64> = Vec::new();
-
-        let mut i = 0;
-        while i < p {
-            if self.evaluate(i).is_divisible_u64(p) {
-                roots.push(i);
-            }
-            i += 1;
-        }
-
-        roots
+        GfPolynomial::from_mp_polynomial(self, p).roots()
     }
 
     // Multiplies f and g mod self. "mod" means here, whenever we encounter a power of x greater or
     // equal to the degree of self, we subtract some multiple of self to make this power disappear.
     pub fn mul_mod(&self, f: &MpPolynomial, g: &MpPolynomial) -> MpPolynomial {
         let d = self.degree();
+        let (modulus, f, g) = (self.padded(d), f.padded(d), g.padded(d));
 
         // Initialize result with the leading coefficient of f times g. This means, the powers
         // of x present in the result are actually shifted up by d - 1.
-        let mut result = MpPolynomial::new();
-        for i in 0..d {
-            result[i] = (&g[i] * &f[d - 1]).complete();
+        let mut result = MpPolynomial::with_len(d);
+        let r = &mut result.0[..d];
+        for (c, g_i) in r.iter_mut().zip(g.iter()) {
+            *c = (g_i * &f[d - 1]).complete();
         }
 
         // In each iteration, the leading coffiecient is converted to lower order terms, and the
         // i-th coefficient of self times rhs is added. Thus, the shift in the exponents of powers
         // of x is reduced by 1.
         for i in (0..d - 1).rev() {
-            let mut leading_coefficient = Integer::new();
-            for coefficient in result.coefficients_mut().iter_mut().take(d) {
-                swap(&mut leading_coefficient, coefficient);
-            }
+            r.rotate_right(1);
+            let leading_coefficient = take(&mut r[0]);
 
-            for j in 0..d {
-                result[j] += &g[j] * &f[i];
-                result[j] -= &leading_coefficient * &self[j];
+            for ((c, g_j), modulus_j) in r.iter_mut().zip(g.iter()).zip(modulus.iter()) {
+                *c += g_j * &f[i];
+                *c -= &leading_coefficient * modulus_j;
             }
         }
 
         result
     }
 
+    // The first len coefficients, padded with zeros if fewer are stored.
+    fn padded(&self, len: usize) -> Cow<'_, [Integer]> {
+        if self.0.len() >= len {
+            Cow::Borrowed(&self.0[..len])
+        } else {
+            let mut coefficients = self.0.clone();
+            coefficients.resize(len, Integer::new());
+            Cow::Owned(coefficients)
+        }
+    }
+
+    // Returns the monic polynomial c^(d - 1) * f(x / c), where c is the leading coefficient of f.
+    // Its roots are c times the roots of f.
+    pub fn monic(&self) -> MpPolynomial {
+        let d = self.degree();
+        let mut g = MpPolynomial::with_len(d + 1);
+        let mut c = Integer::from(1);
+        for i in (0..d).rev() {
+            g[i] = (&self[i] * &c).complete();
+            c *= &self[d];
+        }
+        g[d] = Integer::from(1);
+        g
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn rem(mut self, p: &Integer) -> MpPolynomial {
         for coefficient in self.coefficients_mut() {
             *coefficient %= p;
@@ -109,47 +140,62 @@ impl MpPolynomial {
 
 impl Polynomial<Integer> for MpPolynomial {
     fn degree(&self) -> usize {
-        let mut d = MAX_DEGREE;
-        while d > 0 && self[d] == 0 {
-            d -= 1;
-        }
-        d
+        self.0.iter().rposition(|c| *c != 0).unwrap_or(0)
     }
 
-    fn coefficients(self) -> [Integer; MAX_DEGREE + 1] {
+    fn coefficients(self) -> Vec<Integer> {
         self.0
     }
 
-    fn coefficients_ref(&self) -> &[Integer; MAX_DEGREE + 1] {
+    fn coefficients_ref(&self) -> &[Integer] {
         &self.0
     }
 
-    fn coefficients_mut(&mut self) -> &mut [Integer; MAX_DEGREE + 1] {
+    fn coefficients_mut(&mut self) -> &mut [Integer] {
         &mut self.0
     }
 }
 
+impl PartialEq for MpPolynomial {
+    fn eq(&self, other: &MpPolynomial) -> bool {
+        let d = self.degree();
+        d == other.degree() && self.0[..=d] == other.0[..=d]
+    }
+}
+
+impl Eq for MpPolynomial {}
+
+impl Default for MpPolynomial {
+    fn default() -> MpPolynomial {
+        MpPolynomial::new()
+    }
+}
+
 impl Index<usize> for MpPolynomial {
     type Output = Integer;
 
     fn index(&self, i: usize) -> &Integer {
-        &self.0[i]
+        self.0.get(i).unwrap_or(&ZERO)
     }
 }
 
 impl IndexMut<usize> for MpPolynomial {
     fn index_mut(&mut self, i: usize) -> &mut Integer {
+        if i >= self.0.len() {
+            self.0.resize(i + 1, Integer::new());
+        }
         &mut self.0[i]
     }
 }
 
 impl From<&GfPolynomial> for MpPolynomial {
     fn from(f: &GfPolynomial) -> MpPolynomial {
-        let mut r = MpPolynomial::new();
-        for (i, coefficient) in f.coefficients_ref().iter().enumerate() {
-            r[i] = Integer::from(*coefficient);
-        }
-        r
+        MpPolynomial(
+            f.coefficients_ref()
+                .iter()
+                .map(|c| Integer::from(*c))
+                .collect(),
+        )
     }
 }
 
@@ -182,3 +228,239 @@ pub fn select(n: &Integer, params: &Params) -> (MpPolynomial, Integer) {
 
     (f, m)
 }
+
+// A polynomial pair in the .poly format of msieve and CADO-NFS: the algebraic polynomial f and the
+// rational polynomial Y1 * x + Y0, which have a common root modulo n. The lattice sieve adapts the
+// shape of the sieved region to the skewness.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolynomialPair {
+    pub n: Integer,
+    pub f: MpPolynomial,
+    pub y0: Integer,
+    pub y1: Integer,
+    pub skew: f64,
+}
+
+impl PolynomialPair {
+    // The pair of f and x - m, as chosen by select.
+    pub fn new(n: &Integer, f: MpPolynomial, m: &Integer) -> PolynomialPair {
+        let skew = polyselect::optimal_skew(&f);
+        PolynomialPair {
+            n: n.clone(),
+            f,
+            y0: (-m).complete(),
+            y1: Integer::from(1),
+            skew,
+        }
+    }
+
+    // The rational polynomial Y1 * x + Y0.
+    pub fn g(&self) -> MpPolynomial {
+        let mut g = MpPolynomial::new();
+        g[0] = self.y0.clone();
+        g[1] = self.y1.clone();
+        g
+    }
+
+    // Checks that f and Y1 * x + Y0 have a common root modulo n, i.e. that their resultant
+    // sum c_i * (-Y0)^i * Y1^(d - i) is divisible by n.
+    pub fn check(&self) -> Result<(), String> {
+        if self.n < 2 {
+            return Err(format!("invalid n = {}", self.n));
+        }
+        if self.y1 == 0 {
+            return Err("the rational polynomial has degree 0".to_string());
+        }
+        if self.y0.clone().gcd(&self.y1) != 1 {
+            return Err("the coefficients of the rational polynomial are not coprime".to_string());
+        }
+        let d = self.f.degree();
+        if d == 0 {
+            return Err("the algebraic polynomial has degree 0".to_string());
+        }
+
+        let mut resultant = Integer::new();
+        let mut u = Integer::from(1);
+        for i in 0..=d {
+            let v = self
+                .y1
+                .clone()
+                .pow_mod(&Integer::from(d - i), &self.n)
+                .unwrap();
+            resultant += &self.f[i] * u.clone() * v;
+            resultant %= &self.n;
+            u = (u * (-&self.y0).complete()) % &self.n;
+        }
+        if resultant != 0 {
+            return Err(format!(
+                "the polynomials have no common root modulo n = {}",
+                self.n
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Display for PolynomialPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "n: {}", self.n)?;
+        writeln!(f, "skew: {:.3}", self.skew)?;
+        for i in 0..=self.f.degree() {
+            writeln!(f, "c{}: {}", i, self.f[i])?;
+        }
+        writeln!(f, "Y0: {}", self.y0)?;
+        writeln!(f, "Y1: {}", self.y1)
+    }
+}
+
+impl FromStr for PolynomialPair {
+    type Err = String;
+
+    // Parses lines of the form "name: value", where the names are n, skew, c0 to c8, Y0 and Y1.
+    // Empty lines and comments starting with '#' are skipped. The result is not checked.
+    fn from_str(s: &str) -> Result<PolynomialPair, String> {
+        let mut n: Option<Integer> = None;
+        let mut f = MpPolynomial::new();
+        let (mut y0, mut y1): (Option<Integer>, Option<Integer>) = (None, None);
+        let mut skew: Option<f64> = None;
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| format!("line {}: expected name: value", i + 1))?;
+            let (name, value) = (name.trim(), value.trim());
+            let integer = || {
+                value
+                    .parse::<Integer>()
+                    .map_err(|_| format!("line {}: invalid integer {}", i + 1, value))
+            };
+
+            match name {
+                "n" => n = Some(integer()?),
+                "Y0" => y0 = Some(integer()?),
+                "Y1" => y1 = Some(integer()?),
+                "skew" => {
+                    skew = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("line {}: invalid skewness {}", i + 1, value))?,
+                    )
+                }
+                // The polynomial type of CADO-NFS, which is always gnfs or snfs.
+                "type" => (),
+                _ => {
+                    let Some(k) = name.strip_prefix('c').and_then(|k| k.parse::<usize>().ok())
+                    else {
+                        return Err(format!("line {}: unknown name {}", i + 1, name));
+                    };
+                    let c = integer()?;
+                    if k > MAX_DEGREE {
+                        if c != 0 {
+                            return Err(format!(
+                                "line {}: polynomials of degree above {} are not supported",
+                                i + 1,
+                                MAX_DEGREE
+                            ));
+                        }
+                    } else {
+                        f[k] = c;
+                    }
+                }
+            }
+        }
+
+        let missing = |name: &str| format!("{} is missing", name);
+        let skew = skew.unwrap_or_else(|| polyselect::optimal_skew(&f));
+        Ok(PolynomialPair {
+            n: n.ok_or_else(|| missing("n"))?,
+            f,
+            y0: y0.ok_or_else(|| missing("Y0"))?,
+            y1: y1.ok_or_else(|| missing("Y1"))?,
+            skew,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_and_write_poly_file() {
+        let n = Integer::from(1000000007u64 * 1000000009);
+        let params = Params::new(&n);
+        let (f, m) = select(&n, &params);
+        let pair = PolynomialPair::new(&n, f.clone(), &m);
+        assert_eq!(pair.check(), Ok(()));
+
+        let parsed: PolynomialPair = pair.to_string().parse().unwrap();
+        assert_eq!(parsed.f, f);
+        assert_eq!(parsed.y0, -m.clone());
+        assert_eq!(parsed.y1, 1);
+        assert_eq!(parsed.check(), Ok(()));
+
+        // A file as written by CADO-NFS, for 2 * x^2 + 1 and 3 * x - 1 with the common root 1 / 3
+        // modulo n = 11.
+        let s = "# comment\nn: 11\ntype: gnfs\nskew: 0.707\nc0: 1\nc1: 0\nc2: 2\nY0: -1\nY1: 3\n";
+        let parsed: PolynomialPair = s.parse().unwrap();
+        assert_eq!(
+            (parsed.f[0].clone(), parsed.f[2].clone()),
+            (1.into(), 2.into())
+        );
+        assert_eq!(parsed.skew, 0.707);
+        assert_eq!(parsed.check(), Ok(()));
+
+        let s = "n: 11\nc0: 1\nc2: 2\nY0: -2\nY1: 3\n";
+        assert!(s.parse::<PolynomialPair>().unwrap().check().is_err());
+        for s in ["n: 11\nc0: 1\nY1: 3", "n: 11\nY0: 1\nY1: 3\nfoo: 1", "n 11"] {
+            assert!(s.parse::<PolynomialPair>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn high_degree_polynomials() {
+        let power = |k: usize| {
+            let mut f = MpPolynomial::new();
+            f[k] = Integer::from(1);
+            f
+        };
+
+        // Arithmetic modulo x^7 + 3 * x^2 - 5: x^3 * x^4 = -3 * x^2 + 5 and
+        // x^6 * x^6 = 5 * x^5 + 9 * x^2 - 15.
+        let mut m = power(7);
+        m[2] = Integer::from(3);
+        m[0] = Integer::from(-5);
+        assert_eq!(m.degree(), 7);
+        let mut h = MpPolynomial::new();
+        h[2] = Integer::from(-3);
+        h[0] = Integer::from(5);
+        assert_eq!(m.mul_mod(&power(3), &power(4)), h);
+        let mut h = power(5);
+        h[5] = Integer::from(5);
+        h[2] = Integer::from(9);
+        h[0] = Integer::from(-15);
+        assert_eq!(m.mul_mod(&power(6), &power(6)), h);
+
+        // Trailing zeros don't matter.
+        let mut f = power(2);
+        f[8] = Integer::ZERO;
+        assert_eq!(f, power(2));
+        assert_ne!(f, power(3));
+
+        // x^5 - x - 1 is irreducible modulo 5, x^6 + 1 is not.
+        let mut f = power(5);
+        f[1] = Integer::from(-1);
+        f[0] = Integer::from(-1);
+        assert!(GfPolynomial::from_mp_polynomial(&f, 5).is_irreducible());
+        let mut f = power(6);
+        f[0] = Integer::from(1);
+        assert!(!GfPolynomial::from_mp_polynomial(&f, 5).is_irreducible());
+
+        let s = "n: 11\nc0: 1\nc6: 1\nY0: -1\nY1: 1\n";
+        assert_eq!(s.parse::<PolynomialPair>().unwrap().f, f);
+    }
+}
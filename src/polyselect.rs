@@ -0,0 +1,656 @@
+use std::{f64::consts::PI, sync::OnceLock, time::Instant};
+
+use log::{debug, info};
+use rug::{
+    ops::{Pow, RemRounding},
+    Complete, Integer,
+};
+
+use crate::{
+    nt,
+    params::Params,
+    polynomial::{self, MpPolynomial, Polynomial, PolynomialPair},
+};
+
+// Leading coefficients of f tried besides 1 are the first multiples of this. Small primes dividing
+// the leading coefficient give projective roots, which improve alpha.
+const LEADING_COEFFICIENT_STEP: u64 = 60;
+
+// The leading coefficient of g is a product of at most two primes below this bound, for which
+// a_d * x^d = n has a root.
+const RATIONAL_PRIME_BOUND: u64 = 100;
+const RATIONAL_LEADING_COEFFICIENT_MAX: u64 = 1000;
+
+// For each unit of params.polyselect_effort, this many leading coefficients of f are searched and
+// this many candidates are size optimized. One candidate per unit is root optimized.
+const LEADING_COEFFICIENTS_PER_EFFORT: u64 = 8;
+const SIZE_CANDIDATES_PER_EFFORT: usize = 4;
+
+// Root optimization sieves rotations with |j1| <= ROTATION_J1_MAX and |j0| <= ROTATION_J0_MAX with
+// the primes below ROOT_SIEVE_BOUND, and ranks the best ROTATION_CANDIDATES of them by Murphy E.
+const ROTATION_J1_MAX: i64 = 8;
+const ROTATION_J0_MAX: i64 = 1024;
+const ROOT_SIEVE_BOUND: u64 = 100;
+const ROTATION_CANDIDATES: usize = 4;
+
+// Multiple roots modulo p are lifted to at most this power of p when computing alpha.
+const VALUATION_DEPTH: u32 = 8;
+
+// Murphy E is computed with alpha over the primes below ALPHA_BOUND, as an average over this many
+// points on the boundary of the sieve region.
+const ALPHA_BOUND: u64 = 2000;
+const MURPHY_E_POINTS: usize = 1000;
+
+// Dickman's rho is tabulated with this many steps per unit up to DICKMAN_RHO_MAX, and is taken to
+// be 0 above.
+const DICKMAN_RHO_STEPS: usize = 256;
+const DICKMAN_RHO_MAX: usize = 32;
+
+fn coefficients_f64(f: &MpPolynomial) -> Vec<f64> {
+    f.coefficients_ref()[..=f.degree()]
+        .iter()
+        .map(|c| c.to_f64())
+        .collect()
+}
+
+// The logarithm of the L2 norm of the homogenized polynomial sum c_i * x^i * y^(d - i) on the
+// rectangle |x| <= sqrt(s), |y| <= 1 / sqrt(s), where s is the skewness.
+fn lognorm_f64(c: &[f64], skew: f64) -> f64 {
+    let d = c.len() - 1;
+    let b: Vec<f64> = c
+        .iter()
+        .enumerate()
+        .map(|(i, c)| c * skew.powf(i as f64 - d as f64 / 2.0))
+        .collect();
+
+    // The integral of x^k over [-1, 1] is 2 / (k + 1) for even k and 0 for odd k.
+    let mut integral = 0.0;
+    for i in 0..=d {
+        for j in (i % 2..=d).step_by(2) {
+            integral += b[i] * b[j] * 4.0 / ((i + j + 1) * (2 * d - i - j + 1)) as f64;
+        }
+    }
+    0.5 * integral.ln()
+}
+
+// Minimizes the L2 norm over the skewness s >= 1, first on a grid of log(s) and then by golden
+// section search around the best grid point.
+fn optimal_skew_f64(c: &[f64]) -> f64 {
+    const GRID_STEP: f64 = 0.25;
+
+    let d = c.len() - 1;
+    let upper = (0..d)
+        .filter(|&i| c[i] != 0.0)
+        .map(|i| (c[i] / c[d]).abs().ln() / (d - i) as f64)
+        .fold(0.0, f64::max)
+        + 1.0;
+    let norm = |t: f64| lognorm_f64(c, t.exp());
+
+    let mut t = 0.0;
+    let mut x = GRID_STEP;
+    while x <= upper {
+        if norm(x) < norm(t) {
+            t = x;
+        }
+        x += GRID_STEP;
+    }
+
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    let (mut lo, mut hi) = ((t - GRID_STEP).max(0.0), t + GRID_STEP);
+    for _ in 0..24 {
+        let (x0, x1) = (hi - ratio * (hi - lo), lo + ratio * (hi - lo));
+        if norm(x0) < norm(x1) {
+            hi = x1;
+        } else {
+            lo = x0;
+        }
+    }
+    ((lo + hi) / 2.0).exp()
+}
+
+// The logarithm of the L2 norm of f at the given skewness, as defined by Murphy.
+pub fn lognorm(f: &MpPolynomial, skew: f64) -> f64 {
+    lognorm_f64(&coefficients_f64(f), skew)
+}
+
+// The skewness s >= 1 for which the L2 norm of f is smallest.
+pub fn optimal_skew(f: &MpPolynomial) -> f64 {
+    let c = coefficients_f64(f);
+    if c.len() == 1 {
+        return 1.0;
+    }
+    optimal_skew_f64(&c)
+}
+
+fn residues(f: &MpPolynomial, p: u64) -> Vec<u64> {
+    f.coefficients_ref()[..=f.degree()]
+        .iter()
+        .map(|c| c.mod_u(p as u32) as u64)
+        .collect()
+}
+
+fn evaluate_mod(f: &[u64], x: u64, p: u64) -> u64 {
+    f.iter().rev().fold(0, |y, c| (y * x + c) % p)
+}
+
+// Returns f(x + k).
+pub(crate) fn translate(f: &MpPolynomial, k: &Integer) -> MpPolynomial {
+    let d = f.degree();
+    let mut f = f.clone();
+    for i in 0..d {
+        for j in (i..d).rev() {
+            let c = (&f[j + 1] * k).complete();
+            f[j] += c;
+        }
+    }
+    f
+}
+
+// Returns f(r + p * x).
+fn substitute(f: &MpPolynomial, r: u64, p: u64) -> MpPolynomial {
+    let mut f = translate(f, &Integer::from(r));
+    let mut power = Integer::from(1);
+    for c in f.coefficients_mut() {
+        *c *= &power;
+        power *= p;
+    }
+    f
+}
+
+// The expected valuation at p of f(x) for a uniformly distributed p-adic integer x. A simple root
+// modulo p contributes 1 / (p - 1), multiple roots are lifted recursively.
+fn average_valuation(f: &MpPolynomial, p: u64, depth: u32) -> f64 {
+    let mut f = f.clone();
+    let mut valuation = 0.0;
+    while f
+        .coefficients_ref()
+        .iter()
+        .all(|c| c.is_divisible_u(p as u32))
+    {
+        if f.coefficients_ref().iter().all(|c| *c == 0) {
+            return valuation + depth as f64;
+        }
+        for c in f.coefficients_mut() {
+            *c /= p;
+        }
+        valuation += 1.0;
+    }
+    if depth == 0 {
+        return valuation;
+    }
+
+    let (fp, dfp) = (residues(&f, p), residues(&f.derivative(), p));
+    for r in 0..p {
+        if evaluate_mod(&fp, r, p) == 0 {
+            if evaluate_mod(&dfp, r, p) != 0 {
+                valuation += 1.0 / (p - 1) as f64;
+            } else {
+                valuation += average_valuation(&substitute(&f, r, p), p, depth - 1) / p as f64;
+            }
+        }
+    }
+    valuation
+}
+
+// Murphy's alpha over the primes below bound (CADO-NFS uses 2000): the sum of
+// (1 / (p - 1) - e_p) * log(p), where e_p is the expected valuation at p of the homogenized f at a
+// random coprime pair, and 1 / (p - 1) that of a random integer. Negative values mean that the
+// values of f are smooth more often than random integers of the same size.
+pub fn alpha(f: &MpPolynomial, bound: u64) -> f64 {
+    let d = f.degree();
+    let mut reversed = MpPolynomial::new();
+    for i in 0..=d {
+        reversed[i] = f[d - i].clone();
+    }
+
+    nt::primes_below(bound)
+        .into_iter()
+        .map(|p| {
+            // A coprime pair (a, b) has p | b with probability 1 / (p + 1). Otherwise a / b is a
+            // random p-adic integer, else b / a is a random multiple of p.
+            let affine = average_valuation(f, p, VALUATION_DEPTH);
+            let projective = average_valuation(&substitute(&reversed, 0, p), p, VALUATION_DEPTH);
+            let expected = (p as f64 * affine + projective) / (p + 1) as f64;
+            (1.0 / (p - 1) as f64 - expected) * (p as f64).ln()
+        })
+        .sum()
+}
+
+// Dickman's rho function: the probability that a random integer x is x^(1 / u)-smooth. It is
+// tabulated by integrating rho'(u) = -rho(u - 1) / u with the trapezoidal rule.
+fn dickman_rho(u: f64) -> f64 {
+    static TABLE: OnceLock<Vec<f64>> = OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        let h = 1.0 / DICKMAN_RHO_STEPS as f64;
+        let mut rho = vec![1.0; DICKMAN_RHO_STEPS * DICKMAN_RHO_MAX + 1];
+        for i in DICKMAN_RHO_STEPS + 1..rho.len() {
+            let (u0, u1) = ((i - 1) as f64 * h, i as f64 * h);
+            rho[i] = rho[i - 1]
+                - h / 2.0 * (rho[i - 1 - DICKMAN_RHO_STEPS] / u0 + rho[i - DICKMAN_RHO_STEPS] / u1);
+        }
+        rho
+    });
+
+    if u <= 1.0 {
+        return 1.0;
+    }
+    let x = u * DICKMAN_RHO_STEPS as f64;
+    let i = x as usize;
+    if i + 1 >= table.len() {
+        return 0.0;
+    }
+    let t = x - i as f64;
+    table[i] * (1.0 - t) + table[i + 1] * t
+}
+
+// An upper bound for the k-th prime, used as smoothness bound for a factor base of size k.
+fn nth_prime_bound(k: usize) -> f64 {
+    let k = (k as f64).max(6.0);
+    k * (k.ln() + k.ln().ln())
+}
+
+// Murphy's E score of the pair f, g at the given skewness: the probability that both norms are
+// smooth, averaged over points (a, b) on the ellipse a = sqrt(area * s) * cos(t),
+// b = sqrt(area / s) * sin(t), where the values of f and g are corrected by their alpha. Larger is
+// better. The smoothness bounds and the area are those of the lattice sieve with params.
+pub fn murphy_e(f: &MpPolynomial, g: &MpPolynomial, skew: f64, params: &Params) -> f64 {
+    let bounds = (
+        nth_prime_bound(params.algebraic_base_size).ln(),
+        nth_prime_bound(params.rational_base_size).ln(),
+    );
+    let area = (params.sieve_array_size * params.lattice_rows) as f64 * params.special_q_max as f64;
+    let sides = [(f, bounds.0), (g, bounds.1)]
+        .map(|(f, bound)| (coefficients_f64(f), alpha(f, ALPHA_BOUND), bound));
+
+    let mut e = 0.0;
+    for k in 0..MURPHY_E_POINTS {
+        let t = PI * (k as f64 + 0.5) / MURPHY_E_POINTS as f64;
+        let (a, b) = (
+            (area * skew).sqrt() * t.cos(),
+            (area / skew).sqrt() * t.sin(),
+        );
+        e += sides
+            .iter()
+            .map(|(c, alpha, bound)| {
+                let d = c.len() - 1;
+                let value: f64 = c
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| c * a.powi(i as i32) * b.powi((d - i) as i32))
+                    .sum();
+                dickman_rho((value.abs().max(1.0).ln() + alpha) / bound)
+            })
+            .product::<f64>();
+    }
+    e / MURPHY_E_POINTS as f64
+}
+
+fn score(pair: &PolynomialPair, params: &Params) -> f64 {
+    murphy_e(&pair.f, &pair.g(), pair.skew, params)
+}
+
+fn size(pair: &PolynomialPair) -> f64 {
+    lognorm(&pair.f, optimal_skew(&pair.f))
+}
+
+fn translate_pair(pair: &PolynomialPair, k: i64) -> PolynomialPair {
+    let k = Integer::from(k);
+    PolynomialPair {
+        f: translate(&pair.f, &k),
+        y0: &pair.y0 + &pair.y1 * k,
+        ..pair.clone()
+    }
+}
+
+// Returns the pair with f replaced by f + (j1 * x + j0) * g, which has the same root modulo n.
+fn rotate_pair(pair: &PolynomialPair, j1: i64, j0: i64) -> PolynomialPair {
+    let mut f = pair.f.clone();
+    f[2] += &pair.y1 * Integer::from(j1);
+    f[1] += &pair.y0 * Integer::from(j1) + &pair.y1 * Integer::from(j0);
+    f[0] += &pair.y0 * Integer::from(j0);
+    PolynomialPair { f, ..pair.clone() }
+}
+
+// Writes n = sum a_i * m^i * p^(d - i) with the given leading coefficient a_d, where
+// a_d * m^d = n mod p. From the top, each a_i is chosen in its residue class modulo p as close as
+// possible to the remaining part of n divided by m^i, so that f and p * x - m have the common root
+// m / p modulo n.
+fn expand(n: &Integer, d: usize, a_d: u64, p: u64, m: &Integer) -> PolynomialPair {
+    let mut f = MpPolynomial::new();
+    f[d] = Integer::from(a_d);
+    let mut r = (n - Integer::from(a_d) * m.clone().pow(d as u32)) / p;
+
+    for i in (1..d).rev() {
+        let m_i = m.clone().pow(i as u32);
+        let (mut c, _) = r.clone().div_rem_round(m_i.clone());
+        if p > 1 {
+            let m_i_inverse = nt::invert(m_i.mod_u(p as u32) as u64, p).unwrap();
+            let target = nt::mod_mul(r.mod_u(p as u32) as u64, m_i_inverse, p) as i64;
+            let mut shift = (target - c.mod_u(p as u32) as i64).rem_euclid(p as i64);
+            if shift > p as i64 / 2 {
+                shift -= p as i64;
+            }
+            c += shift;
+        }
+        r = (r - &c * m_i) / p;
+        f[i] = c;
+    }
+    f[0] = r;
+
+    PolynomialPair {
+        n: n.clone(),
+        f,
+        y0: (-m).complete(),
+        y1: Integer::from(p),
+        skew: 1.0,
+    }
+}
+
+// The primes q below RATIONAL_PRIME_BOUND with q^2 <= bound and the roots of a_d * x^d = n modulo
+// q^2, lifted from the simple roots modulo q.
+fn rational_primes(n: &Integer, d: usize, a_d: u64, bound: u64) -> Vec<(u64, Vec<Integer>)> {
+    let mut primes = Vec::new();
+    for q in nt::primes_below(RATIONAL_PRIME_BOUND) {
+        if q * q > bound || (a_d * d as u64).is_multiple_of(q) || n.is_divisible_u(q as u32) {
+            continue;
+        }
+        let (n_q, a_q) = (n.mod_u(q as u32) as u64, a_d % q);
+        let mut h = MpPolynomial::new();
+        h[d] = Integer::from(a_d);
+        h[0] = (-n).complete();
+        let roots: Vec<Integer> = (1..q)
+            .filter(|&x| nt::mod_mul(a_q, nt::mod_exp(x, d as u64, q), q) == n_q)
+            .map(|x| nt::hensel_lift(&h, q, x, 2).unwrap())
+            .collect();
+        if !roots.is_empty() {
+            primes.push((q, roots));
+        }
+    }
+    primes
+}
+
+// Size optimization: a local search over translations x -> x + k and rotations by x * g and g,
+// minimizing the L2 norm of f at its optimal skewness.
+fn size_optimize(pair: PolynomialPair) -> PolynomialPair {
+    let d = pair.f.degree();
+    let mut best = pair;
+    let mut best_size = size(&best);
+    let mut steps: [i64; 3] = [1 << 20; 3];
+    if d < 3 {
+        // A rotation by x * g would change the leading coefficient.
+        steps[1] = 0;
+    }
+
+    while steps.iter().any(|&step| step != 0) {
+        for (i, step) in steps.iter_mut().enumerate() {
+            if *step == 0 {
+                continue;
+            }
+            let candidates = [*step, -*step].map(|step| match i {
+                0 => translate_pair(&best, step),
+                1 => rotate_pair(&best, step, 0),
+                _ => rotate_pair(&best, 0, step),
+            });
+            match candidates
+                .into_iter()
+                .map(|candidate| (size(&candidate), candidate))
+                .find(|(size, _)| *size < best_size)
+            {
+                Some((size, candidate)) => (best_size, best) = (size, candidate),
+                None => *step /= 2,
+            }
+        }
+    }
+    best
+}
+
+// Root optimization: searches rotations f + (j1 * x + j0) * g with small j1 and j0 for the one with
+// the best Murphy E, preselecting them by the sum of L2 norm and alpha. The contribution of the
+// simple roots modulo small primes to alpha is computed for all j0 at once by sieving, since x is a
+// root of f + j0 * g modulo p if and only if j0 = -f(x) / g(x) mod p.
+fn root_optimize(pair: &PolynomialPair, params: &Params) -> (f64, PolynomialPair) {
+    let d = pair.f.degree();
+    let skew = optimal_skew(&pair.f);
+    let ratio =
+        |k: usize| (pair.f[d].to_f64() * skew.powi(k as i32) / pair.y0.to_f64()).abs() as i64;
+    let j0_max = ratio(d).clamp(1, ROTATION_J0_MAX);
+    let j1_max = if d >= 3 {
+        ratio(d - 1).clamp(0, ROTATION_J1_MAX)
+    } else {
+        0
+    };
+    let width = 2 * j0_max as usize + 1;
+    let (y0, y1) = (pair.y0.to_f64(), pair.y1.to_f64());
+
+    let mut rotations: Vec<(f64, i64, i64)> = Vec::with_capacity((2 * j1_max as usize + 1) * width);
+    let mut estimate = vec![0.0; width];
+    for j1 in -j1_max..=j1_max {
+        let rotated = rotate_pair(pair, j1, 0);
+        estimate.fill(0.0);
+        for p in nt::primes_below(ROOT_SIEVE_BOUND) {
+            let contribution = (p as f64).ln() * p as f64 / (p * p - 1) as f64;
+            let fp = residues(&rotated.f, p);
+            let (y0p, y1p) = (
+                pair.y0.mod_u(p as u32) as u64,
+                pair.y1.mod_u(p as u32) as u64,
+            );
+            for x in 0..p {
+                let gx = (y1p * x + y0p) % p;
+                if gx == 0 {
+                    continue;
+                }
+                let j = (p - evaluate_mod(&fp, x, p)) * nt::mod_inv(gx, p) % p;
+                let mut k = ((j as i64 + j0_max) % p as i64) as usize;
+                while k < width {
+                    estimate[k] -= contribution;
+                    k += p as usize;
+                }
+            }
+        }
+
+        let mut c = coefficients_f64(&rotated.f);
+        for (k, alpha) in estimate.iter().enumerate() {
+            let j0 = (k as i64 - j0_max) as f64;
+            let (c0, c1) = (c[0], c[1]);
+            c[0] += j0 * y0;
+            c[1] += j0 * y1;
+            rotations.push((lognorm_f64(&c, skew) + alpha, j1, k as i64 - j0_max));
+            (c[0], c[1]) = (c0, c1);
+        }
+    }
+
+    rotations.sort_by(|x, y| x.0.total_cmp(&y.0));
+    rotations
+        .into_iter()
+        .take(ROTATION_CANDIDATES)
+        .map(|(_, j1, j0)| {
+            let mut rotated = rotate_pair(pair, j1, j0);
+            rotated.skew = optimal_skew(&rotated.f);
+            (score(&rotated, params), rotated)
+        })
+        .max_by(|x, y| x.0.total_cmp(&y.0))
+        .unwrap()
+}
+
+// Keeps the count best candidates.
+fn keep_best(candidates: &mut Vec<(f64, PolynomialPair)>, count: usize) {
+    candidates.sort_by(|x, y| x.0.total_cmp(&y.0));
+    candidates.truncate(count);
+}
+
+// Polynomial selection after Kleinjung. For leading coefficients a_d of f and leading coefficients
+// p of g, which are products of small primes, m is chosen close to (n / a_d)^(1 / d) with
+// a_d * m^d = n mod p^2, and f is obtained by expanding n in base m / p. The smallest candidates
+// (by L2 norm) are size optimized and then root optimized, and the one with the best Murphy E is
+// returned, or the base m pair if it scores better. The amount of work is fixed by
+// params.polyselect_effort, so that the result doesn't depend on the speed of the machine.
+pub fn select(n: &Integer, params: &Params) -> PolynomialPair {
+    let (f, m) = polynomial::select(n, params);
+    let mut naive = PolynomialPair::new(n, f, &m);
+    if params.polyselect_effort == 0 {
+        return naive;
+    }
+
+    let start = Instant::now();
+    let d = params.polynomial_degree;
+
+    naive.skew = optimal_skew(&naive.f);
+    let naive_score = score(&naive, params);
+    let mut candidates: Vec<(f64, PolynomialPair)> = vec![(size(&naive), naive.clone())];
+    let mut searched = 0;
+
+    let size_candidates = SIZE_CANDIDATES_PER_EFFORT * params.polyselect_effort;
+    let leading_coefficients = (0..LEADING_COEFFICIENTS_PER_EFFORT
+        * params.polyselect_effort as u64)
+        .map(|k| (k * LEADING_COEFFICIENT_STEP).max(1));
+    for a_d in leading_coefficients {
+        let m0 = (n / a_d).complete().root(d as u32);
+        if m0 < 2 {
+            break;
+        }
+        let p_max = m0
+            .clone()
+            .sqrt()
+            .to_u64()
+            .unwrap_or(u64::MAX)
+            .min(RATIONAL_LEADING_COEFFICIENT_MAX);
+
+        // The leading coefficients p of g with the roots of a_d * x^d = n modulo p^2.
+        let primes = rational_primes(n, d, a_d, p_max);
+        let mut moduli: Vec<(u64, Vec<Integer>)> = vec![(1, vec![Integer::new()])];
+        for (i, (q1, roots1)) in primes.iter().enumerate() {
+            moduli.push((*q1, roots1.clone()));
+            for (q2, roots2) in &primes[i + 1..] {
+                if q1 * q2 > p_max {
+                    break;
+                }
+                let (q1_2, q2_2) = (Integer::from(q1 * q1), Integer::from(q2 * q2));
+                let mut roots = Vec::with_capacity(roots1.len() * roots2.len());
+                for r1 in roots1 {
+                    for r2 in roots2 {
+                        roots.push(nt::crt(r1, &q1_2, r2, &q2_2).unwrap());
+                    }
+                }
+                moduli.push((q1 * q2, roots));
+            }
+        }
+
+        for (p, roots) in moduli {
+            let p2 = Integer::from(p * p);
+            for r in roots {
+                // The m = r mod p^2 closest to m0.
+                let mut delta = (r - &m0).rem_euc(&p2);
+                if delta > (&p2 / 2u32).complete() {
+                    delta -= &p2;
+                }
+                let pair = expand(n, d, a_d, p, &(&m0 + delta));
+                candidates.push((size(&pair), pair));
+                searched += 1;
+            }
+            if candidates.len() > 4 * size_candidates {
+                keep_best(&mut candidates, size_candidates);
+            }
+        }
+    }
+    keep_best(&mut candidates, size_candidates);
+    debug!(
+        "polynomial selection: {} candidates searched, best L2 norm {:.2}",
+        searched, candidates[0].0
+    );
+
+    let mut optimized: Vec<(f64, PolynomialPair)> = candidates
+        .into_iter()
+        .map(|(_, pair)| {
+            let pair = size_optimize(pair);
+            (size(&pair), pair)
+        })
+        .collect();
+    keep_best(&mut optimized, params.polyselect_effort);
+
+    let best = optimized
+        .iter()
+        .map(|(_, pair)| root_optimize(pair, params))
+        .max_by(|x, y| x.0.total_cmp(&y.0))
+        .unwrap();
+
+    // The base m pair may have been dropped by its L2 norm, so it is returned if none of the
+    // optimized candidates beats it.
+    let (e, pair) = match best {
+        (e, _) if e < naive_score => (naive_score, naive),
+        best => best,
+    };
+    debug_assert_eq!(pair.check(), Ok(()));
+    info!(
+        "selected polynomials with Murphy E {:.3e} (base m: {:.3e}), L2 norm {:.2}, alpha {:.2} \
+         and skewness {:.1} in {:.2?}",
+        e,
+        naive_score,
+        size(&pair),
+        alpha(&pair.f, ALPHA_BOUND),
+        pair.skew,
+        start.elapsed()
+    );
+    pair
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alpha_of_simple_polynomials() {
+        // x^2 + 1 has two roots modulo the primes 1 mod 4, one modulo 2 and none otherwise.
+        let mut f = MpPolynomial::new();
+        f[0] = Integer::from(1);
+        f[2] = Integer::from(1);
+        let expected: f64 = nt::primes_below(200)
+            .into_iter()
+            .map(|p| {
+                let roots = match p % 4 {
+                    1 => 2.0,
+                    3 => 0.0,
+                    // The valuation of a^2 + b^2 at 2 is 1 if a and b are odd, which happens with
+                    // probability 1 / 3, and 0 otherwise.
+                    _ => 0.5,
+                };
+                let p = p as f64;
+                (1.0 / (p - 1.0) - roots * p / (p * p - 1.0)) * p.ln()
+            })
+            .sum();
+        assert!((alpha(&f, 200) - expected).abs() < 1e-9);
+
+        // The coefficients of a balanced polynomial are best at skewness 1, those of
+        // x^2 + 2^20 at 2^10.
+        assert!((optimal_skew(&f) - 1.0).abs() < 1e-3);
+        f[0] = Integer::from(1 << 20);
+        assert!((optimal_skew(&f) / 1024.0 - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn dickman_rho_values() {
+        assert_eq!(dickman_rho(0.5), 1.0);
+        for (u, rho) in [
+            (2.0, 1.0 - 2f64.ln()),
+            (3.0, 0.0486083882),
+            (5.0, 3.5472470e-4),
+        ] {
+            assert!((dickman_rho(u) / rho - 1.0).abs() < 1e-2, "{}", u);
+        }
+        assert_eq!(dickman_rho(40.0), 0.0);
+    }
+
+    #[test]
+    fn select_polynomial() {
+        let n = Integer::from_str_radix("2305843025354595015495857657", 10).unwrap();
+        let params = Params::new(&n);
+        let pair = select(&n, &params);
+        assert_eq!(pair.check(), Ok(()));
+        assert_eq!(select(&n, &params), pair);
+
+        let (f, m) = polynomial::select(&n, &params);
+        let mut naive = PolynomialPair::new(&n, f, &m);
+        naive.skew = optimal_skew(&naive.f);
+        assert!(size(&pair) < size(&naive));
+        assert!(score(&pair, &params) > score(&naive, &params));
+    }
+}
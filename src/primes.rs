@@ -0,0 +1,111 @@
+// Numbers sieved at once. The memory used is bounded by this and the primes up to the square root
+// of the largest prime returned.
+const SEGMENT_SIZE: u64 = 1 << 16;
+
+// The primes in [low, high). All primes p with p^2 < high must be in sieving.
+fn sieve_segment(low: u64, high: u64, sieving: &[u64]) -> Vec<u64> {
+    let mut is_composite = vec![false; (high - low) as usize];
+    for &p in sieving {
+        if p * p >= high {
+            break;
+        }
+        let mut i = match low.div_ceil(p).checked_mul(p) {
+            Some(i) => i.max(p * p),
+            None => continue,
+        };
+        while i < high {
+            is_composite[(i - low) as usize] = true;
+            i = match i.checked_add(p) {
+                Some(i) => i,
+                None => break,
+            };
+        }
+    }
+
+    (low.max(2)..high)
+        .filter(|n| !is_composite[(n - low) as usize])
+        .collect()
+}
+
+// Iterates over the primes in increasing order with a segmented sieve of Eratosthenes.
+pub struct Primes {
+    // All primes below sieved_to, which are used for sieving the segments.
+    sieving: Vec<u64>,
+    sieved_to: u64,
+    segment: Vec<u64>,
+    index: usize,
+    // The start of the next segment.
+    low: u64,
+}
+
+impl Primes {
+    // The primes from start on.
+    pub fn new(start: u64) -> Primes {
+        Primes {
+            sieving: Vec::new(),
+            sieved_to: 2,
+            segment: Vec::new(),
+            index: 0,
+            low: start,
+        }
+    }
+
+    // Extends the sieving primes to all primes p with p^2 < high.
+    fn extend_sieving(&mut self, high: u64) {
+        let needed = (high - 1).isqrt() + 1;
+        while self.sieved_to < needed {
+            let next = self
+                .sieved_to
+                .saturating_mul(self.sieved_to)
+                .min(self.sieved_to + SEGMENT_SIZE)
+                .min(needed);
+            let primes = sieve_segment(self.sieved_to, next, &self.sieving);
+            self.sieving.extend(primes);
+            self.sieved_to = next;
+        }
+    }
+}
+
+impl Iterator for Primes {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        while self.index == self.segment.len() {
+            // u64::MAX is not a prime.
+            if self.low == u64::MAX {
+                return None;
+            }
+            let high = self.low.saturating_add(SEGMENT_SIZE);
+            self.extend_sieving(high);
+            self.segment = sieve_segment(self.low, high, &self.sieving);
+            self.index = 0;
+            self.low = high;
+        }
+        self.index += 1;
+        Some(self.segment[self.index - 1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rug::{integer::IsPrime, Integer};
+
+    use super::*;
+
+    #[test]
+    fn primes_in_ranges() {
+        let primes: Vec<u64> = Primes::new(0).take(10).collect();
+        assert_eq!(primes, vec![2, 3, 5, 7, 11, 13, 17, 19, 23, 29]);
+        assert_eq!(Primes::new(2).take_while(|&p| p < 1_000_000).count(), 78498);
+
+        // Across several segments and far from 2, compared with a primality test.
+        for start in [SEGMENT_SIZE - 10, 1 << 40] {
+            let end = start + 3 * SEGMENT_SIZE;
+            let expected: Vec<u64> = (start..end)
+                .filter(|&n| Integer::from(n).is_probably_prime(30) != IsPrime::No)
+                .collect();
+            let primes: Vec<u64> = Primes::new(start).take_while(|&p| p < end).collect();
+            assert_eq!(primes, expected);
+        }
+    }
+}
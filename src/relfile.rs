@@ -0,0 +1,160 @@
+use std::{fmt::Display, str::FromStr};
+
+use rug::{integer::IntegerExt64, Integer};
+
+use crate::{nfs, nt, polynomial::MpPolynomial};
+
+// Primes below this bound may be left out of a relation read from a file, since some tools only
+// list the larger primes. They are found again by trial division.
+const OMITTED_PRIME_BOUND: u64 = 1000;
+
+// A relation in the text format of msieve and CADO-NFS: "a,b:p1,p2,...:q1,q2,..." with the primes
+// dividing the rational and the algebraic norm in hexadecimal, each repeated according to its
+// multiplicity. These tools use pairs a - b * alpha, while this crate uses a + b * alpha, so the
+// sign of a is flipped when a relation is read or written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelationLine {
+    pub a: i64,
+    pub b: u64,
+    pub rational: Vec<u64>,
+    pub algebraic: Vec<u64>,
+}
+
+fn format_primes(primes: &[u64]) -> String {
+    let primes: Vec<String> = primes.iter().map(|p| format!("{:x}", p)).collect();
+    primes.join(",")
+}
+
+fn parse_primes(s: &str) -> Result<Vec<u64>, String> {
+    if s.is_empty() {
+        return Ok(Vec::new());
+    }
+    s.split(',')
+        .map(|p| u64::from_str_radix(p, 16).map_err(|_| format!("invalid prime {}", p)))
+        .collect()
+}
+
+// Divides the listed primes out of the norm and checks that only primes below OMITTED_PRIME_BOUND
+// remain.
+fn check_norm(mut norm: Integer, primes: &[u64], side: &str) -> Result<(), String> {
+    norm.abs_mut();
+    for &p in primes {
+        if !nt::miller_rabin(p) {
+            return Err(format!("{:x} is not a prime", p));
+        }
+        if !norm.is_divisible_u64(p) {
+            return Err(format!("{:x} does not divide the {} norm", p, side));
+        }
+        norm /= p;
+    }
+    for p in nt::primes_below(OMITTED_PRIME_BOUND) {
+        norm.remove_factor_mut(&Integer::from(p));
+    }
+    if norm != 1 {
+        return Err(format!("the {} norm is not completely factored", side));
+    }
+    Ok(())
+}
+
+impl RelationLine {
+    // Checks that (a, b) is a coprime pair and that the primes listed are the factorizations of the
+    // rational norm for the linear polynomial g and the norm of a + b * alpha, where f(alpha) = 0.
+    pub fn check(&self, g: &MpPolynomial, f: &MpPolynomial) -> Result<(), String> {
+        if self.b == 0 || nt::gcd(self.a.unsigned_abs(), self.b) != 1 {
+            return Err(format!("{} and {} are not coprime", self.a, self.b));
+        }
+        check_norm(nfs::norm(g, self.a, self.b), &self.rational, "rational")?;
+        check_norm(nfs::norm(f, self.a, self.b), &self.algebraic, "algebraic")
+    }
+}
+
+impl Display for RelationLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{},{}:{}:{}",
+            -self.a,
+            self.b,
+            format_primes(&self.rational),
+            format_primes(&self.algebraic)
+        )
+    }
+}
+
+impl FromStr for RelationLine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RelationLine, String> {
+        let mut parts = s.trim().split(':');
+        let (Some(pair), Some(rational), Some(algebraic), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(format!(
+                "expected a,b:rational primes:algebraic primes: {}",
+                s
+            ));
+        };
+        let (a, b) = pair
+            .split_once(',')
+            .ok_or_else(|| format!("expected a,b: {}", pair))?;
+        let a: i64 = a.parse().map_err(|_| format!("invalid integer {}", a))?;
+        let b: u64 = b.parse().map_err(|_| format!("invalid integer {}", b))?;
+
+        Ok(RelationLine {
+            a: a.checked_neg()
+                .ok_or_else(|| format!("{} is too large", a))?,
+            b,
+            rational: parse_primes(rational)?,
+            algebraic: parse_primes(algebraic)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_and_check_relations() {
+        // f = x^2 + 1 and g = x - 1000, so the norms of a + b * m and a + b * alpha are
+        // a + 1000 * b and a^2 + b^2.
+        let mut f = MpPolynomial::new();
+        f[0] = Integer::from(1);
+        f[2] = Integer::from(1);
+        let mut g = MpPolynomial::new();
+        g[0] = Integer::from(-1000);
+        g[1] = Integer::from(1);
+
+        // (-3, 101): 100997 = 13 * 17 * 457 and 10210 = 2 * 5 * 1021.
+        let line: RelationLine = "3,101:d,11,1c9:2,5,3fd".parse().unwrap();
+        assert_eq!(
+            line,
+            RelationLine {
+                a: -3,
+                b: 101,
+                rational: vec![13, 17, 457],
+                algebraic: vec![2, 5, 1021],
+            }
+        );
+        assert_eq!(line.to_string(), "3,101:d,11,1c9:2,5,3fd");
+        assert_eq!(line.check(&g, &f), Ok(()));
+
+        // Primes below 1000 may be left out.
+        let line: RelationLine = "3,101::3fd".parse().unwrap();
+        assert_eq!(line.check(&g, &f), Ok(()));
+
+        for s in [
+            "3,101:d,11,1c9:2,5",
+            "3,101:d,11,1c9:2,5,3fd,3fd",
+            "3,101:d,11,1c9,7:2,5,3fd",
+            "3,101:dd,1c9:2,5,3fd",
+            "6,202:d,11,1c9:2,5,3fd",
+        ] {
+            let line: RelationLine = s.parse().unwrap();
+            assert!(line.check(&g, &f).is_err(), "{}", s);
+        }
+        for s in ["3,101:d", "3;101::", "3,101:d,xyz:", "3,-101::"] {
+            assert!(s.parse::<RelationLine>().is_err(), "{}", s);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use rug::{Complete, Integer};
+
+// Pollard's rho method with Brent's cycle detection, using the map x -> x^2 + c. Gives up after
+// roughly max_iterations evaluations of the map.
+pub fn rho(n: &Integer, c: u64, max_iterations: u64) -> Option<Integer> {
+    const BATCH_SIZE: u64 = 128;
+
+    let f = |x: &Integer| (x.square_ref().complete() + c) % n;
+
+    let mut y = Integer::from(2);
+    let mut x = y.clone();
+    let mut ys = y.clone();
+    let mut q = Integer::from(1);
+    let mut g = Integer::from(1);
+    let mut r: u64 = 1;
+    let mut iterations: u64 = 0;
+
+    while g == 1 {
+        x.clone_from(&y);
+        for _ in 0..r {
+            y = f(&y);
+        }
+
+        // Multiply the differences together and compute the gcd only once per batch.
+        let mut k: u64 = 0;
+        while k < r && g == 1 {
+            ys.clone_from(&y);
+            for _ in 0..BATCH_SIZE.min(r - k) {
+                y = f(&y);
+                q *= (&x - &y).complete();
+                q %= n;
+            }
+            g = q.gcd_ref(n).complete();
+            k += BATCH_SIZE;
+        }
+
+        iterations += 2 * r;
+        if iterations > max_iterations && g == 1 {
+            return None;
+        }
+        r <<= 1;
+    }
+
+    // The batch may have overshot, so go back to its start and proceed one step at a time.
+    if g == *n {
+        loop {
+            ys = f(&ys);
+            g = (&x - &ys).complete().gcd(n);
+            if g != 1 {
+                break;
+            }
+        }
+    }
+
+    if g == *n {
+        None
+    } else {
+        Some(g)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rho_semiprime() {
+        let n = Integer::from(1000003u64 * 998244353u64);
+        let mut g = None;
+        for c in 1.. {
+            g = rho(&n, c, 1 << 20);
+            if g.is_some() {
+                break;
+            }
+        }
+        let g = g.unwrap();
+        assert!(g == 1000003 || g == 998244353);
+    }
+}
@@ -0,0 +1,539 @@
+use std::collections::{HashMap, HashSet};
+
+use log::{debug, info};
+use rand::{seq::SliceRandom, thread_rng};
+use rug::{integer::IntegerExt64, Complete, Integer};
+
+use crate::{lanczos, linalg::CscMatrixBuilder, nt, params::OVERSQUARENESS, primes::Primes};
+
+#[derive(Clone, Copy)]
+struct SiqsParams {
+    factor_base_size: usize,
+    // x ranges over [-sieve_size, sieve_size) for each polynomial.
+    sieve_size: usize,
+    // Relations with a cofactor below this multiple of the largest factor base prime are kept.
+    large_prime_multiplier: u64,
+}
+
+const PARAM_TABLE: [(u32, SiqsParams); 8] = [
+    (
+        80,
+        SiqsParams {
+            factor_base_size: 150,
+            sieve_size: 1 << 14,
+            large_prime_multiplier: 30,
+        },
+    ),
+    (
+        100,
+        SiqsParams {
+            factor_base_size: 200,
+            sieve_size: 1 << 15,
+            large_prime_multiplier: 40,
+        },
+    ),
+    (
+        128,
+        SiqsParams {
+            factor_base_size: 450,
+            sieve_size: 1 << 15,
+            large_prime_multiplier: 40,
+        },
+    ),
+    (
+        160,
+        SiqsParams {
+            factor_base_size: 1000,
+            sieve_size: 1 << 16,
+            large_prime_multiplier: 50,
+        },
+    ),
+    (
+        183,
+        SiqsParams {
+            factor_base_size: 2000,
+            sieve_size: 1 << 16,
+            large_prime_multiplier: 50,
+        },
+    ),
+    (
+        200,
+        SiqsParams {
+            factor_base_size: 3000,
+            sieve_size: 1 << 16,
+            large_prime_multiplier: 60,
+        },
+    ),
+    (
+        233,
+        SiqsParams {
+            factor_base_size: 8000,
+            sieve_size: 3 << 16,
+            large_prime_multiplier: 80,
+        },
+    ),
+    (
+        266,
+        SiqsParams {
+            factor_base_size: 25000,
+            sieve_size: 3 << 16,
+            large_prime_multiplier: 100,
+        },
+    ),
+];
+
+// Primes below this bound are not sieved with, their contribution is accounted for in the
+// threshold.
+const SMALL_PRIME_BOUND: u64 = 30;
+const THRESHOLD_FUDGE: i32 = 3;
+
+impl SiqsParams {
+    fn new(n: &Integer) -> SiqsParams {
+        let bits = n.significant_bits();
+        for (bits_lim, params) in PARAM_TABLE {
+            if bits <= bits_lim {
+                return params;
+            }
+        }
+        PARAM_TABLE.last().unwrap().1
+    }
+}
+
+struct FactorBasePrime {
+    p: u64,
+    sqrt: u64, // square root of kn mod p
+    log: u8,
+}
+
+// A relation (a * x + b)^2 = y^2 = (-1)^negative * (product of factor base primes to the given
+// exponents) * large_prime^2 mod kn. large_prime is 1 for relations without a large prime.
+struct Relation {
+    y: Integer,
+    negative: bool,
+    factors: Vec<(usize, u32)>,
+    large_prime: u64,
+}
+
+// The Knuth-Schroeppel function estimates the contribution of small primes to the smoothness of
+// values of the polynomials when factoring k * n.
+fn choose_multiplier(n: &Integer) -> u64 {
+    const MULTIPLIERS: [u64; 33] = [
+        1, 3, 5, 7, 11, 13, 15, 17, 19, 21, 23, 29, 31, 33, 35, 37, 39, 41, 43, 47, 51, 53, 55, 57,
+        59, 61, 65, 67, 69, 71, 73, 77, 79,
+    ];
+    let primes = nt::primes_below(1000);
+
+    let mut best = (f64::MIN, 1);
+    for k in MULTIPLIERS {
+        let kn = (n * k).complete();
+        let mut score = -0.5 * (k as f64).ln();
+        score += match kn.mod_u(8) {
+            1 => 2.0,
+            5 => 1.0,
+            _ => 0.5,
+        } * 2f64.ln();
+
+        for &p in primes.iter().skip(1) {
+            let log_p = (p as f64).ln();
+            if k % p == 0 {
+                score += log_p / p as f64;
+            } else if nt::jacobi(kn.mod_u64(p), p) == 1 {
+                score += 2.0 * log_p / (p - 1) as f64;
+            }
+        }
+
+        if score > best.0 {
+            best = (score, k);
+        }
+    }
+
+    best.1
+}
+
+fn factor_base(kn: &Integer, k: u64, size: usize) -> Vec<FactorBasePrime> {
+    let mut base = vec![FactorBasePrime {
+        p: 2,
+        sqrt: 1,
+        log: 1,
+    }];
+
+    for p in Primes::new(3) {
+        if base.len() >= size {
+            break;
+        }
+        let r = kn.mod_u64(p);
+        if k.is_multiple_of(p) || nt::jacobi(r, p) == 1 {
+            base.push(FactorBasePrime {
+                p,
+                sqrt: nt::sqrt_mod(r, p).unwrap(),
+                log: (p as f64).log2().round() as u8,
+            });
+        }
+    }
+
+    base
+}
+
+// Chooses the indices of factor base primes, whose product a is close to target_log2, trying to
+// avoid values of a that were already used.
+fn choose_a(
+    base: &[FactorBasePrime],
+    k: u64,
+    target_log2: f64,
+    used: &mut HashSet<Integer>,
+) -> Option<(Integer, Vec<usize>)> {
+    // Prefer primes of about 11 bits, but at most the upper quarter of the factor base.
+    let max_log2 = (base[base.len() * 3 / 4].p as f64).log2().min(11.0);
+    let s = ((target_log2 / max_log2).ceil() as usize).max(1);
+    let q_log2 = target_log2 / s as f64;
+
+    let mut candidates: Vec<usize> = Vec::new();
+    let mut width = 0.5;
+    while candidates.len() < 2 * s + 4 && width < 8.0 {
+        candidates = (0..base.len())
+            .filter(|&i| {
+                let p = base[i].p;
+                p > SMALL_PRIME_BOUND
+                    && !k.is_multiple_of(p)
+                    && ((p as f64).log2() - q_log2).abs() <= width
+            })
+            .collect();
+        width *= 2.0;
+    }
+    if candidates.len() < s {
+        return None;
+    }
+
+    let mut rng = thread_rng();
+    for _ in 0..100 {
+        candidates.shuffle(&mut rng);
+        let mut indices: Vec<usize> = candidates[..s - 1].to_vec();
+        let log2: f64 = indices.iter().map(|&i| (base[i].p as f64).log2()).sum();
+
+        // Choose the last prime such that the product gets as close to the target as possible.
+        let last = *candidates[s - 1..]
+            .iter()
+            .min_by(|&&i, &&j| {
+                let ei = (log2 + (base[i].p as f64).log2() - target_log2).abs();
+                let ej = (log2 + (base[j].p as f64).log2() - target_log2).abs();
+                ei.partial_cmp(&ej).unwrap()
+            })
+            .unwrap();
+        indices.push(last);
+        indices.sort_unstable();
+
+        let a = indices.iter().fold(Integer::from(1), |a, &i| a * base[i].p);
+        if used.insert(a.clone()) {
+            return Some((a, indices));
+        }
+    }
+
+    None
+}
+
+// Sieves over all polynomials (a * x + b)^2 - kn belonging to a, appending found relations to
+// relations and storing relations with a large prime in partials until a partner is found.
+#[allow(clippy::too_many_arguments)]
+fn sieve_a(
+    n: &Integer,
+    kn: &Integer,
+    base: &[FactorBasePrime],
+    a: &Integer,
+    a_indices: &[usize],
+    params: &SiqsParams,
+    threshold: u8,
+    large_prime_bound: u64,
+    relations: &mut Vec<Relation>,
+    partials: &mut HashMap<u64, Relation>,
+) {
+    let m = params.sieve_size as i64;
+    let s = a_indices.len();
+
+    // b is the sum of the b_terms, where b_l is congruent to sqrt(kn) mod q_l and to 0 mod the
+    // other primes dividing a.
+    let b_terms: Vec<Integer> = a_indices
+        .iter()
+        .map(|&i| {
+            let q = base[i].p;
+            let a_l = (a / q).complete();
+            let mut gamma = base[i].sqrt * nt::mod_inv(a_l.mod_u64(q), q) % q;
+            if gamma > q / 2 {
+                gamma = q - gamma;
+            }
+            a_l * gamma
+        })
+        .collect();
+    let mut b = b_terms.iter().fold(Integer::new(), |b, b_l| b + b_l);
+
+    // Roots of the polynomial a * x^2 + 2 * b * x + c mod each prime, and the amounts by which
+    // they change when switching b_l's sign.
+    let mut roots: Vec<(u64, u64)> = vec![(0, 0); base.len()];
+    let mut b_ainv: Vec<Vec<u64>> = vec![vec![0; base.len()]; s];
+    let mut sieved: Vec<bool> = vec![false; base.len()];
+    for (i, fb) in base.iter().enumerate() {
+        let p = fb.p;
+        if p < SMALL_PRIME_BOUND || a.is_divisible_u64(p) {
+            continue;
+        }
+        sieved[i] = true;
+        let a_inv = nt::mod_inv(a.mod_u64(p), p);
+        let b_mod = b.mod_u64(p);
+        roots[i] = (
+            (fb.sqrt + p - b_mod) % p * a_inv % p,
+            ((2 * p - fb.sqrt - b_mod) % p) * a_inv % p,
+        );
+        for l in 0..s {
+            b_ainv[l][i] = 2 * b_terms[l].mod_u64(p) % p * a_inv % p;
+        }
+    }
+
+    let mut sieve_array: Vec<u8> = vec![0; 2 * params.sieve_size];
+
+    for poly in 0..1usize << (s - 1) {
+        if poly != 0 {
+            // Gray code enumeration of the sign combinations of the b_l.
+            let v = poly.trailing_zeros() as usize;
+            let subtract = (poly >> (v + 1)) & 1 == 0;
+            for (i, root) in roots.iter_mut().enumerate() {
+                if sieved[i] {
+                    let p = base[i].p;
+                    let delta = if subtract {
+                        b_ainv[v][i]
+                    } else {
+                        p - b_ainv[v][i]
+                    };
+                    root.0 = (root.0 + delta) % p;
+                    root.1 = (root.1 + delta) % p;
+                }
+            }
+            if subtract {
+                b -= 2u32 * &b_terms[v];
+            } else {
+                b += 2u32 * &b_terms[v];
+            }
+        }
+        debug_assert!(((&b * &b).complete() - kn).is_divisible(a));
+        let c = ((&b * &b).complete() - kn) / a;
+
+        sieve_array.fill(0);
+        for (i, fb) in base.iter().enumerate() {
+            if !sieved[i] {
+                continue;
+            }
+            let p = fb.p;
+            let offset = (m as u64) % p;
+            let (r1, r2) = roots[i];
+            for r in [r1, r2] {
+                let mut j = ((r + offset) % p) as usize;
+                while j < sieve_array.len() {
+                    sieve_array[j] = sieve_array[j].wrapping_add(fb.log);
+                    j += p as usize;
+                }
+                if r1 == r2 {
+                    break;
+                }
+            }
+        }
+
+        for (j, &log) in sieve_array.iter().enumerate() {
+            if log < threshold {
+                continue;
+            }
+
+            let x = j as i64 - m;
+            let mut value = (a * x).complete() * x + (&b * (2 * x)).complete() + &c;
+            let negative = value < 0;
+            value = value.abs();
+
+            let mut factors: Vec<(usize, u32)> = Vec::new();
+            for (i, fb) in base.iter().enumerate() {
+                let p = fb.p;
+                if sieved[i] {
+                    let x_mod = (x.rem_euclid(p as i64)) as u64;
+                    if x_mod != roots[i].0 && x_mod != roots[i].1 {
+                        continue;
+                    }
+                }
+                if value.is_divisible_u64(p) {
+                    let e = value.remove_factor_mut(&Integer::from(p));
+                    factors.push((i, e));
+                }
+            }
+
+            // The factorization of (a * x + b)^2 - kn is a times the one of value.
+            for &i in a_indices {
+                match factors.iter_mut().find(|(j, _)| *j == i) {
+                    Some((_, e)) => *e += 1,
+                    None => factors.push((i, 1)),
+                }
+            }
+            factors.sort_unstable();
+
+            let y = ((a * x).complete() + &b) % n;
+            if value == 1 {
+                relations.push(Relation {
+                    y,
+                    negative,
+                    factors,
+                    large_prime: 1,
+                });
+            } else if value < large_prime_bound {
+                let large_prime = value.to_u64().unwrap();
+                let relation = Relation {
+                    y,
+                    negative,
+                    factors,
+                    large_prime,
+                };
+                match partials.remove(&large_prime) {
+                    Some(partner) => relations.push(combine(n, relation, partner)),
+                    None => {
+                        partials.insert(large_prime, relation);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Multiplies two relations with the same large prime.
+fn combine(n: &Integer, r: Relation, s: Relation) -> Relation {
+    let mut factors = r.factors;
+    for (i, e) in s.factors {
+        match factors.iter_mut().find(|(j, _)| *j == i) {
+            Some((_, f)) => *f += e,
+            None => factors.push((i, e)),
+        }
+    }
+    factors.sort_unstable();
+
+    Relation {
+        y: r.y * s.y % n,
+        negative: r.negative ^ s.negative,
+        factors,
+        large_prime: r.large_prime,
+    }
+}
+
+// Factors n with the self-initializing quadratic sieve. n must be odd, composite and not a perfect
+// power. Returns a nontrivial factor of n, if one is found.
+pub fn factorize(n: &Integer) -> Option<Integer> {
+    let params = SiqsParams::new(n);
+    let k = choose_multiplier(n);
+    let kn = (n * k).complete();
+    let base = factor_base(&kn, k, params.factor_base_size);
+    let largest_prime = base.last().unwrap().p;
+    let large_prime_bound = largest_prime * params.large_prime_multiplier;
+
+    info!(
+        "using the multiplier {} and a factor base of {} primes up to {}",
+        k,
+        base.len(),
+        largest_prime
+    );
+
+    // The values of the polynomials are about sieve_size * sqrt(kn / 2) in size, of which the
+    // primes in the factor base should make up all but a large prime.
+    let kn_log2 = kn.significant_bits() as f64;
+    let target_log2 = (kn_log2 + 1.0) / 2.0 - (params.sieve_size as f64).log2();
+    let threshold = ((params.sieve_size as f64).log2() + kn_log2 / 2.0
+        - 0.5
+        - (large_prime_bound as f64).log2())
+    .round() as i32
+        - THRESHOLD_FUDGE;
+    let threshold = threshold.clamp(1, u8::MAX as i32) as u8;
+
+    let mut relations: Vec<Relation> = Vec::new();
+    let mut partials: HashMap<u64, Relation> = HashMap::new();
+    let mut used: HashSet<Integer> = HashSet::new();
+    let needed = base.len() + 1 + OVERSQUARENESS;
+
+    while relations.len() < needed {
+        let (a, a_indices) = choose_a(&base, k, target_log2, &mut used)?;
+        sieve_a(
+            n,
+            &kn,
+            &base,
+            &a,
+            &a_indices,
+            &params,
+            threshold,
+            large_prime_bound,
+            &mut relations,
+            &mut partials,
+        );
+        debug!(
+            "collected {} relations and {} partial relations",
+            relations.len(),
+            partials.len()
+        );
+    }
+    relations.truncate(needed);
+    info!("collected {} relations", relations.len());
+
+    let mut matrix_builder = CscMatrixBuilder::new();
+    matrix_builder.set_num_rows(base.len() + 1);
+    for relation in &relations {
+        let mut ones_pos: Vec<usize> = Vec::new();
+        if relation.negative {
+            ones_pos.push(0);
+        }
+        for &(i, e) in &relation.factors {
+            if e & 1 == 1 {
+                ones_pos.push(i + 1);
+            }
+        }
+        matrix_builder.add_col(ones_pos);
+    }
+
+    let (mat, num_dependencies) = lanczos::find_dependencies(&matrix_builder.build());
+
+    for i in 0..num_dependencies {
+        let mut x = Integer::from(1);
+        let mut y = Integer::from(1);
+        let mut exponents: Vec<u64> = vec![0; base.len()];
+
+        for (j, relation) in relations.iter().enumerate() {
+            if (mat[j] >> i) & 1 == 1 {
+                x *= &relation.y;
+                x %= n;
+                y *= relation.large_prime;
+                y %= n;
+                for &(l, e) in &relation.factors {
+                    exponents[l] += e as u64;
+                }
+            }
+        }
+
+        for (l, e) in exponents.into_iter().enumerate() {
+            debug_assert_eq!(e & 1, 0);
+            if e != 0 {
+                y *= Integer::from(base[l].p)
+                    .pow_mod(&Integer::from(e >> 1), n)
+                    .unwrap();
+                y %= n;
+            }
+        }
+
+        let g = (&x - &y).complete().gcd(n);
+        if g != 1 && g != *n {
+            return Some(g);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn siqs_semiprime() {
+        let p = Integer::from(1000000007u64) * 998244353u64;
+        let q = Integer::from((1u64 << 61) - 1);
+        let n = (&p * &q).complete();
+        let g = factorize(&n).unwrap();
+        assert!(g != 1 && g != n && n.is_divisible(&g));
+    }
+}
@@ -0,0 +1,286 @@
+use std::{fmt::Display, str::FromStr};
+
+use log::info;
+use rug::{ops::Pow, Integer};
+
+use crate::{
+    params::Params,
+    polynomial::{MpPolynomial, PolynomialPair},
+    polyselect, sqrt,
+};
+
+// Numbers a * b^k + c are recognized with b up to RECOGNIZED_BASE_MAX and a and |c| up to
+// RECOGNIZED_COEFFICIENT_MAX.
+const RECOGNIZED_BASE_MAX: u64 = 100;
+const RECOGNIZED_COEFFICIENT_MAX: u64 = 1 << 16;
+
+// A number a * b^k + c with small a, b and c, e.g. a Cunningham number b^k +- 1. The special
+// number field sieve factors it and its divisors with a polynomial pair with tiny coefficients.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpecialForm {
+    pub a: u64,
+    pub b: u64,
+    pub k: u32,
+    pub c: i64,
+}
+
+// Whether a * x^d + c is irreducible over the rationals. By Capelli's theorem, x^d - t with
+// t = -c * a^(d - 1) is reducible if and only if t is a p-th power for a prime p dividing d, or
+// 4 divides d and t = -4 * s^4.
+fn binomial_is_irreducible(a: &Integer, c: &Integer, d: u32) -> bool {
+    let t = -(c * a.clone().pow(d - 1));
+    let is_power = |t: &Integer, p: u32| {
+        if t.is_negative() && p.is_multiple_of(2) {
+            return false;
+        }
+        let (root, rem) = t.clone().abs().root_rem(Integer::new(), p);
+        rem == 0 && root != 0
+    };
+    if (2..=d)
+        .any(|p| d.is_multiple_of(p) && (2..p).all(|q| !p.is_multiple_of(q)) && is_power(&t, p))
+    {
+        return false;
+    }
+    if d.is_multiple_of(4) && t.is_negative() {
+        let (s, rem) = (-t).div_rem(Integer::from(4));
+        if rem == 0 && is_power(&s, 4) {
+            return false;
+        }
+    }
+    true
+}
+
+impl SpecialForm {
+    pub fn value(&self) -> Integer {
+        Integer::from(self.a) * Integer::from(self.b).pow(self.k) + self.c
+    }
+
+    // The SNFS difficulty, i.e. the size of the special number in bits. It determines the
+    // parameters, instead of the size of the divisor that is factored.
+    pub fn difficulty(&self) -> u32 {
+        self.value().significant_bits()
+    }
+
+    // Writes n as a * b^k + c with a small base b and small a and c, if possible. Of several
+    // representations, the one with the smallest base is chosen.
+    pub fn recognize(n: &Integer) -> Option<SpecialForm> {
+        if n.significant_bits() < 32 {
+            return None;
+        }
+        for b in 2..=RECOGNIZED_BASE_MAX {
+            let b_int = Integer::from(b);
+            let k_max = n.significant_bits() / (b_int.significant_bits() - 1);
+            let mut power = b_int.clone().pow(k_max);
+            for k in (2..=k_max).rev() {
+                let (a, c) = n.clone().div_rem_round(power.clone());
+                if a == 0 {
+                    power /= b;
+                    continue;
+                }
+                if a > RECOGNIZED_COEFFICIENT_MAX {
+                    break;
+                }
+                if c != 0 && c.clone().abs() <= RECOGNIZED_COEFFICIENT_MAX {
+                    return Some(SpecialForm {
+                        a: a.to_u64().unwrap(),
+                        b,
+                        k,
+                        c: c.to_i64().unwrap(),
+                    });
+                }
+                power /= b;
+            }
+        }
+        None
+    }
+
+    // Divisors of the value that follow from the identities x^e - 1 | x^k - 1 for e | k,
+    // x^e + 1 | x^k - 1 for even k / e and x^e + 1 | x^k + 1 for odd k / e, if the value is
+    // b^k - 1 or b^k + 1.
+    pub fn algebraic_factors(&self) -> Vec<Integer> {
+        if self.a != 1 || self.c.abs() != 1 {
+            return Vec::new();
+        }
+        let mut factors = Vec::new();
+        for e in (1..self.k).filter(|e| self.k.is_multiple_of(*e)) {
+            let power = Integer::from(self.b).pow(e);
+            let odd = !(self.k / e).is_multiple_of(2);
+            if self.c == -1 {
+                factors.push(power.clone() - 1);
+            }
+            if (self.c == -1) != odd {
+                factors.push(power + 1);
+            }
+        }
+        factors.retain(|factor| *factor > 1);
+        factors.sort();
+        factors.dedup();
+        factors
+    }
+
+    // Constructs the polynomial pair for n, a divisor of the value. For each degree d from 2 to the
+    // polynomial_degree of the SNFS parameters for the difficulty, k is written as d * q + r or
+    // d * q - r with 0 <= r < d, which gives f = a * b^r * x^d + c or f = a * x^d + c * b^r with
+    // the root b^q modulo the value. The irreducible candidate with the best Murphy E is returned,
+    // leaving out those without an inert prime like x^4 + 1.
+    pub fn polynomial(&self, n: &Integer) -> Option<PolynomialPair> {
+        let params = Params::new_snfs(self.difficulty());
+        let (a, b, c) = (
+            Integer::from(self.a),
+            Integer::from(self.b),
+            Integer::from(self.c),
+        );
+        let mut best: Option<(f64, PolynomialPair)> = None;
+
+        for d in 2..=params.polynomial_degree as u32 {
+            let (q, r) = (self.k / d, self.k % d);
+            let mut candidates = vec![(q, &a * b.clone().pow(r), c.clone())];
+            if r != 0 {
+                candidates.push((q + 1, a.clone(), &c * b.clone().pow(d - r)));
+            }
+
+            for (q, leading, constant) in candidates {
+                if q == 0 || !binomial_is_irreducible(&leading, &constant, d) {
+                    continue;
+                }
+                let mut f = MpPolynomial::new();
+                f[d as usize] = leading;
+                f[0] = constant;
+                let pair = PolynomialPair::new(n, f, &b.clone().pow(q));
+                if pair.check().is_err() {
+                    continue;
+                }
+                let e = polyselect::murphy_e(&pair.f, &pair.g(), pair.skew, &params);
+                // The square root step needs a prime that is inert in the number field. Looking
+                // for one is slow if there is none, so it is only done for improvements.
+                if best.as_ref().is_none_or(|best| e > best.0)
+                    && sqrt::inert_prime(&pair.f).is_some()
+                {
+                    best = Some((e, pair));
+                }
+            }
+        }
+
+        let (e, pair) = best?;
+        info!(
+            "SNFS polynomials for {}: f = {}, g = {} (Murphy E {:.3e})",
+            self,
+            pair.f,
+            pair.g(),
+            e
+        );
+        Some(pair)
+    }
+}
+
+impl Display for SpecialForm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.a != 1 {
+            write!(f, "{}*", self.a)?;
+        }
+        write!(f, "{}^{}", self.b, self.k)?;
+        if self.c > 0 {
+            write!(f, "+")?;
+        }
+        write!(f, "{}", self.c)
+    }
+}
+
+impl FromStr for SpecialForm {
+    type Err = String;
+
+    // Parses forms like 2^137-1 or 3*10^80+7.
+    fn from_str(s: &str) -> Result<SpecialForm, String> {
+        let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let invalid = || format!("expected a*b^k+c, got {}", s);
+
+        let (a, rest) = match s.split_once('*') {
+            Some((a, rest)) => (a.parse().map_err(|_| invalid())?, rest),
+            None => (1, s.as_str()),
+        };
+        let (b, rest) = rest.split_once('^').ok_or_else(invalid)?;
+        let split = rest.find(['+', '-']).ok_or_else(invalid)?;
+        let (k, c) = rest.split_at(split);
+        let c = c.strip_prefix('+').unwrap_or(c);
+
+        let form = SpecialForm {
+            a,
+            b: b.parse().map_err(|_| invalid())?,
+            k: k.parse().map_err(|_| invalid())?,
+            c: c.parse().map_err(|_| invalid())?,
+        };
+        if form.a == 0 || form.b < 2 || form.k == 0 || form.c == 0 {
+            return Err(format!("{} is not of a special form", s));
+        }
+        Ok(form)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polynomial::Polynomial;
+
+    #[test]
+    fn special_forms() {
+        let form: SpecialForm = "3 * 2^100 + 5".parse().unwrap();
+        assert_eq!(
+            form,
+            SpecialForm {
+                a: 3,
+                b: 2,
+                k: 100,
+                c: 5
+            }
+        );
+        assert_eq!(form.to_string(), "3*2^100+5");
+        assert_eq!(SpecialForm::recognize(&form.value()), Some(form));
+        assert_eq!(
+            SpecialForm::recognize(&(Integer::from(10).pow(40) - 1)).map(|f| f.to_string()),
+            Some("10^40-1".to_string())
+        );
+        assert_eq!(
+            SpecialForm::recognize(&Integer::from(1000003u64 * 1000033)),
+            None
+        );
+        for s in ["2^101", "2^-1+1", "x^3+1", "0*2^10+1", "1^10+1"] {
+            assert!(s.parse::<SpecialForm>().is_err(), "{}", s);
+        }
+
+        // 2^12 - 1 = (2^6 - 1)(2^6 + 1) = (2^4 - 1)(2^8 + 2^4 + 1) = ...
+        let form: SpecialForm = "2^12-1".parse().unwrap();
+        let factors = form.algebraic_factors();
+        assert_eq!(factors, vec![3, 5, 7, 9, 15, 63, 65]);
+        assert!(factors.iter().all(|d| form.value().is_divisible(d)));
+        let form: SpecialForm = "2^15+1".parse().unwrap();
+        assert_eq!(form.algebraic_factors(), vec![3, 9, 33]);
+
+        assert!(binomial_is_irreducible(&1.into(), &1.into(), 4));
+        assert!(!binomial_is_irreducible(&1.into(), &(-1).into(), 4));
+        assert!(!binomial_is_irreducible(&1.into(), &4.into(), 4));
+        assert!(!binomial_is_irreducible(&2.into(), &(-16).into(), 3));
+        assert!(binomial_is_irreducible(&2.into(), &(-1).into(), 4));
+    }
+
+    #[test]
+    fn snfs_polynomial() {
+        // 2^128 + 1 = 59649589127497217 * 5704689200685129054721. x^4 + 1 with m = 2^32 has no
+        // inert prime, so 4 * x^3 + 1 with m = 2^42 is used.
+        let form: SpecialForm = "2^128+1".parse().unwrap();
+        let n = form.value();
+        let pair = form.polynomial(&n).unwrap();
+        assert_eq!(pair.check(), Ok(()));
+        assert_eq!(pair.f.to_string(), "4 * x^3 + 0 * x^2 + 0 * x^1 + 1 * x^0");
+        assert_eq!(pair.y0, -(1i64 << 42));
+
+        // A divisor of the value gets the same polynomial.
+        let p = Integer::from(59649589127497217u64);
+        assert_eq!(form.polynomial(&p).unwrap().f, pair.f);
+
+        // The degree is at most the one of the SNFS parameters, 6, although x^7 + 3 has a better
+        // Murphy E.
+        let form: SpecialForm = "2^700+3".parse().unwrap();
+        let pair = form.polynomial(&form.value()).unwrap();
+        assert!(pair.f.degree() <= Params::new_snfs(form.difficulty()).polynomial_degree);
+    }
+}
@@ -9,25 +9,35 @@ use rug::{
 
 use crate::{
     gfpolynomial::GfPolynomial,
-    nt,
     polynomial::{MpPolynomial, Polynomial},
+    primes::Primes,
 };
 
+// The number of primes tried when looking for an inert prime. If the Galois group of f contains a
+// cycle of length deg f, at least one in |G| primes is inert by Chebotarev's density theorem, so f
+// most likely has no inert prime at all if none is found, e.g. x^4 + 1.
+const INERT_PRIME_TRIALS: usize = 1000;
+
+// Finds a prime p that is inert in the number field, which means f is irreducible mod p.
+pub fn inert_prime(f: &MpPolynomial) -> Option<u64> {
+    Primes::new(101010)
+        .take(INERT_PRIME_TRIALS)
+        .find(|p| GfPolynomial::from_mp_polynomial(f, *p).is_irreducible())
+}
+
 // Calculates the algebraic square root of the product of s using q-adic newton iteration.
 // Uses divide and conquer to evaluate the product in O(M log n) time, where M is the time needed
 // to multiply two numbers in the order of magnitude of the result.
 pub fn algebraic_sqrt(s: &MpPolynomial, f: &MpPolynomial) -> Option<MpPolynomial> {
-    let mut p: u64 = 101010;
-
-    // p must be inert in the number field, which means f must be irreducible mod p.
-    while !nt::miller_rabin(p) || !GfPolynomial::from_mp_polynomial(f, p).is_irreducible() {
-        p += 1;
-    }
+    let Some(p) = inert_prime(f) else {
+        warn!("found no inert prime for {}", f);
+        return None;
+    };
     info!("chose the prime for lifting p = {}", p);
 
     let mut r = MpPolynomial::from(&inv_sqrt_mod_p(
-        &GfPolynomial::from_mp_polynomial(&s, p),
-        &GfPolynomial::from_mp_polynomial(&f, p),
+        &GfPolynomial::from_mp_polynomial(s, p),
+        &GfPolynomial::from_mp_polynomial(f, p),
     ));
 
     let num_iterations = (s
@@ -84,7 +94,7 @@ pub fn algebraic_sqrt(s: &MpPolynomial, f: &MpPolynomial) -> Option<MpPolynomial
     }
 
     warn!("newtons method failed");
-    return None;
+    None
 }
 
 pub fn mul_algebraic_integers(integers: &[MpPolynomial], f: &MpPolynomial) -> MpPolynomial {
@@ -143,7 +153,7 @@ fn mul_y_polynomials(
 ) -> (GfPolynomial, GfPolynomial) {
     (
         f.mul_mod(&u.0, &v.0)
-            .add(&f.mul_mod(&f.mul_mod(&u.1, &v.1), &s)),
+            .add(&f.mul_mod(&f.mul_mod(&u.1, &v.1), s)),
         f.mul_mod(&u.0, &v.1).add(&f.mul_mod(&u.1, &v.0)),
     )
 }